@@ -0,0 +1,32 @@
+use std::{env, fs, path::Path};
+
+// rdev comes from a git fork, so its version alone doesn't say which code is running.
+// The lock file has the exact commit, which `--diagnose` reports alongside the app version.
+fn main() {
+    let lock_path = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock_path.display());
+    let rdev_version = fs::read_to_string(&lock_path)
+        .ok()
+        .and_then(|lock| locked_version(&lock, "rdev"))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RDEV_VERSION={}", rdev_version);
+}
+
+// "<version>" or "<version> (<source>)" of a package in the lock file
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|package| value(package, "name") == Some(name))?;
+    let version = value(package, "version")?;
+    Some(match value(package, "source") {
+        Some(source) => format!("{} ({})", version, source),
+        None => version.to_string(),
+    })
+}
+
+fn value<'a>(package: &'a str, key: &str) -> Option<&'a str> {
+    package.lines().find_map(|line| {
+        let (line_key, value) = line.split_once('=')?;
+        (line_key.trim() == key).then(|| value.trim().trim_matches('"'))
+    })
+}
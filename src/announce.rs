@@ -0,0 +1,62 @@
+use std::{
+    process::Command,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{debug, warn};
+
+// The listener is handed the lines scrolled so far in the gesture through this variable
+const LINES_ENV_VAR: &str = "SMOOTHER_SCROLL_LINES";
+
+// Runs a command whenever scrolling has gone another stretch of lines, for example a screen reader
+// script that announces the position. Calls within the minimum interval of the last run are skipped.
+pub struct CommandAnnouncer {
+    // Program first, then its arguments
+    command: Vec<String>,
+    min_interval: Duration,
+    last_run: Mutex<Option<Instant>>,
+}
+
+impl CommandAnnouncer {
+    pub fn new(command: Vec<String>, min_interval: Duration) -> Self {
+        CommandAnnouncer {
+            command,
+            min_interval,
+            last_run: Mutex::new(None),
+        }
+    }
+
+    pub fn announce(&self, lines: i64) {
+        let Some((program, args)) = self.command.split_first() else {
+            return;
+        };
+        {
+            let now = Instant::now();
+            let mut last_run = self.last_run.lock().unwrap();
+            if let Some(last_run) = *last_run {
+                if now.duration_since(last_run) < self.min_interval {
+                    return;
+                }
+            }
+            *last_run = Some(now);
+        }
+
+        debug!("Announcing {} lines", lines);
+        let child = Command::new(program)
+            .args(args)
+            .env(LINES_ENV_VAR, lines.to_string())
+            .spawn();
+        match child {
+            // Waited for on a thread of its own, so that the grab callback never blocks on it
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(error) => warn!(
+                "Failed to run the announce command {:?}: {}",
+                program, error
+            ),
+        }
+    }
+}
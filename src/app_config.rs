@@ -1,39 +1,1088 @@
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::display;
+use crate::emitter::{OutputBackend, ScrollTarget};
+use crate::event_handler::{
+    ColdStart, EventHandlerConfig, RoundingMode, SmoothAxes, Tuning, UncertainAction, WarmupAction,
+};
+use crate::event_log::EventLogFormat;
+use crate::filter::{AxisSmoothing, ReversalPolicy, SmoothingAlgorithm};
+use crate::fling::FlingCurve;
+use crate::hotkey::{parse_key, parse_modifier};
+use crate::logging::LogLevel;
+use crate::notify::notify;
+use crate::profile::Profile;
+use crate::remote_session::RemoteSessionPolicy;
+use crate::units::Lines;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not access the config file {path:?}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("could not parse the config file: {0}")]
+    Parse(#[from] serde_json::Error),
+    #[error("invalid config: {0}")]
+    Validation(String),
+}
+
+pub const CONFIG_ENV_VAR: &str = "SMOOTHER_SCROLL_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "config.json";
+// Time constant of a smoothness of 100
+const MAX_SMOOTHNESS_TIME_CONSTANT_MS: f32 = 200.0;
+
+// Upper limits well past anything useful, but low enough that durations, buffers and
+// timer deadlines built from them can't overflow
+const MAX_MS: f64 = 3_600_000.0;
+const MAX_SECS: f64 = 86_400.0;
+const MAX_LINES: f64 = 10_000.0;
+// Lines per millisecond
+const MAX_SPEED: f64 = 100.0;
+const MAX_MULTIPLIER: f64 = 1000.0;
+const MAX_COUNT: f64 = 100_000.0;
+const TIME_CONSTANT_RANGE_MS: RangeInclusive<f64> = 0.0..=10_000.0;
+const SPEED_RANGE: RangeInclusive<f64> = 0.0..=MAX_SPEED;
+const MULTIPLIER_RANGE: RangeInclusive<f64> = 0.0..=MAX_MULTIPLIER;
+
+// A numeric setting as a number, or None while it is unset
+trait Numeric {
+    fn number(&self) -> Option<f64>;
+}
+
+macro_rules! impl_numeric {
+    ($($type:ty),*) => {
+        $(impl Numeric for $type {
+            fn number(&self) -> Option<f64> {
+                Some(*self as f64)
+            }
+        })*
+    };
+}
+
+impl_numeric!(f32, u16, u32, u64, usize);
+
+impl Numeric for Lines {
+    fn number(&self) -> Option<f64> {
+        self.0.number()
+    }
+}
+
+impl<T: Numeric> Numeric for Option<T> {
+    fn number(&self) -> Option<f64> {
+        self.as_ref().and_then(Numeric::number)
+    }
+}
+
+struct NumericSetting {
+    name: &'static str,
+    range: RangeInclusive<f64>,
+    value: fn(&AppConfig) -> Option<f64>,
+}
+
+// Lists every numeric setting once, with its default and its allowed values.
+// The defaults go into `numeric_defaults`, which `Default` reads, and the ranges into
+// NUMERIC_SETTINGS, which `validate()` and `--init` read.
+macro_rules! numeric_settings {
+    ($($name:ident: $type:ty = $default:expr, $range:expr;)*) => {
+        #[allow(non_upper_case_globals)]
+        mod numeric_defaults {
+            use super::*;
+            $(pub const $name: $type = $default;)*
+        }
+
+        const NUMERIC_SETTINGS: &[NumericSetting] = &[$(NumericSetting {
+            name: stringify!($name),
+            range: $range,
+            value: |config| config.$name.number(),
+        }),*];
+    };
+}
+
+numeric_settings! {
+    time_constant_ms: f32 = 30.0, TIME_CONSTANT_RANGE_MS;
+    smoothness: Option<f32> = None, 0.0..=100.0;
+    soft_start_secs: f32 = 0.0, 0.0..=60.0;
+    reversal_cushion_ms: u64 = 0, 0.0..=MAX_MS;
+    sign_neutral_zone: Lines = Lines(0.0), 0.0..=MAX_LINES;
+    min_speed: f32 = 0.005, SPEED_RANGE;
+    always_pass_above: Lines = Lines::from_wheel_units(3.9), 0.0..=MAX_LINES;
+    high_res_scale: f32 = 8.0, 1.0..=MAX_MULTIPLIER;
+    reversal_toggle_count: u32 = 0, 0.0..=100.0;
+    reversal_toggle_window_ms: u64 = 600, 0.0..=MAX_MS;
+    reversal_toggle_min_distance: Lines = Lines(2.0), 0.0..=MAX_LINES;
+    zoom_step: Lines = Lines(0.0), 0.0..=MAX_LINES;
+    drift_epsilon: Lines = Lines(0.0), 0.0..=MAX_LINES;
+    drift_window_ms: u64 = 2000, 0.0..=MAX_MS;
+    duplicate_window_ms: f32 = 0.0, 0.0..=MAX_MS;
+    click_scroll_cooldown_ms: u64 = 0, 0.0..=MAX_MS;
+    wheel_watchdog_secs: u64 = 120, 0.0..=MAX_SECS;
+    startup_delay_ms: u64 = 0, 0.0..=MAX_MS;
+    max_notches_per_event: u32 = 3, 1.0..=1000.0;
+    output_jitter_epsilon: Lines = Lines(0.0), 0.0..=MAX_LINES;
+    idle_reset_ms: u64 = 5000, 0.0..=MAX_MS;
+    announce_every_lines: f32 = 10.0, 0.0..=MAX_LINES;
+    announce_min_interval_ms: u64 = 500, 0.0..=MAX_MS;
+    heartbeat_secs: u64 = 0, 0.0..=MAX_SECS;
+    history_size: usize = 0, 0.0..=MAX_COUNT;
+    control_port: u16 = 0, 0.0..=65535.0;
+    burst_merge_ms: u64 = 0, 0.0..=MAX_MS;
+    timer_output_hz: u32 = 0, 0.0..=1000.0;
+    timer_output_catch_up_ms: u64 = 30, 0.0..=MAX_MS;
+    own_event_timeout_ms: u64 = 250, 0.0..=MAX_MS;
+    warmup_events: u32 = 0, 0.0..=MAX_COUNT;
+    flush_dropped_after_ms: u64 = 0, 0.0..=MAX_MS;
+    flush_min_distance: Lines = Lines(0.0), 0.0..=MAX_LINES;
+    flush_min_events: u32 = 0, 0.0..=MAX_COUNT;
+    smoothing_velocity_floor: f32 = 0.0, SPEED_RANGE;
+    boost_window_ms: u64 = 0, 0.0..=MAX_MS;
+    boost_multiplier: f32 = 5.0, MULTIPLIER_RANGE;
+    boost_min_distance: Lines = Lines(1.0), 0.0..=MAX_LINES;
+    gear_hysteresis: f32 = 0.1, 0.0..=1.0;
+    max_dropped_distance: Lines = Lines::from_wheel_units(30.0), 0.0..=MAX_LINES;
+    velocity_lock_max_secs: u64 = 300, 0.0..=MAX_SECS;
+    fling_threshold: Lines = Lines(0.0), 0.0..=MAX_LINES;
+    fling_duration_ms: u64 = 1500, 0.0..=MAX_MS;
+    fling_duration_x_ms: Option<u64> = None, 0.0..=MAX_MS;
+    fling_duration_y_ms: Option<u64> = None, 0.0..=MAX_MS;
+    log_boost_secs: u64 = 60, 0.0..=MAX_SECS;
+    max_lines_per_second_x: f32 = 0.0, 0.0..=MAX_LINES;
+    max_lines_per_second_y: f32 = 0.0, 0.0..=MAX_LINES;
+    max_gesture_distance: Lines = Lines(0.0), 0.0..=MAX_LINES;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct AppConfig {
     // run_on_startup: bool,
     pub log_to_file: bool,
+    pub log_level: LogLevel,
+    pub log_panics: bool,
+    // Also reports warnings and errors to the Windows Event Log
+    pub log_to_event_log: bool,
+    pub smoothing: SmoothingAlgorithm,
+    // How quickly older scroll deltas fade out of the smoothing
+    pub time_constant_ms: f32,
+    // One dial from 0 to 100 that overrides time_constant_ms when set
+    pub smoothness: Option<f32>,
+    pub soft_start_secs: f32,
+    pub reversal_cushion_ms: u64,
+    pub reversal_policy: ReversalPolicy,
+    pub sign_neutral_zone: Lines,
+    pub clamp_zero_crossing: bool,
+    // Smoothing of a single axis instead of the global one
+    pub axis_x: Option<AxisConfig>,
+    pub axis_y: Option<AxisConfig>,
+    // Scroll speed in lines per millisecond below which events are dropped
+    pub min_speed: f32,
+    // Deltas at least this large are always kept, regardless of the scroll speed
+    #[serde(alias = "force_start_distance")]
+    pub always_pass_above: Lines,
+    pub bypass_large_deltas: bool,
+    pub inclusive_threshold: bool,
+    // Multiplies the distance and speed thresholds by the speed_multiplier of the active profile
+    pub scale_thresholds_by_multiplier: bool,
+    // Divides the distance and speed thresholds by high_res_scale, for wheels with finer steps
+    pub high_res: bool,
+    pub high_res_scale: f32,
+    // Forces smoothing on or off at launch. When unset, the last runtime choice
+    // from the state file next to the config is used instead.
+    pub enabled: Option<bool>,
+    // Name of the key that toggles smoothing at runtime, for example "ScrollLock".
+    pub toggle_hotkey: Option<String>,
+    // Toggles smoothing by scrolling back and forth this many times, 0 disables it
+    pub reversal_toggle_count: u32,
+    pub reversal_toggle_window_ms: u64,
+    pub reversal_toggle_min_distance: Lines,
+    pub notify_on_toggle: bool,
+    // Modifier that turns scrolling into zooming, "Ctrl", "Shift", "Alt" or "Meta"
+    pub zoom_modifier: Option<String>,
+    pub zoom_step: Lines,
+    // Deltas below this are treated as wheel drift, 0.0 disables drift detection
+    pub drift_epsilon: Lines,
+    pub drift_window_ms: u64,
+    // Events with the same deltas within this many milliseconds of the previous one are dropped
+    pub duplicate_window_ms: f32,
+    // Scroll events this many milliseconds after a mouse button press are dropped, 0 disables it
+    pub click_scroll_cooldown_ms: u64,
+    // Warn if no scroll events arrive this long after startup, 0 disables the warning
+    pub wheel_watchdog_secs: u64,
+    // Waits this long before grabbing the mouse, for launches at login
+    pub startup_delay_ms: u64,
+    pub smooth_axes: SmoothAxes,
+    // Sends the vertical scroll distance as whole notches, for apps that ignore fractional lines
+    pub emit_notches: bool,
+    pub max_notches_per_event: u32,
+    pub coalesce_output: bool,
+    pub output_jitter_epsilon: Lines,
+    pub force_integer_notches: bool,
+    pub idle_reset_ms: u64,
+    pub passthrough_first_tick: bool,
+    pub cold_start: ColdStart,
+    pub emit_scroll_end: bool,
+    // Program and arguments that are run every announce_every_lines lines, empty disables it
+    pub announce_command: Vec<String>,
+    pub announce_every_lines: f32,
+    pub announce_min_interval_ms: u64,
+    // Logs a summary every so often, 0 disables it
+    pub heartbeat_secs: u64,
+    // How many of the last scroll events to keep for looking back at, zero keeps none
+    pub history_size: usize,
+    // Port of the local HTTP control server, 0 disables it
+    pub control_port: u16,
+    // Confirms with a desktop notification that the app is running
+    pub notify_on_start: bool,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    pub profiles: Vec<Profile>,
+    pub burst_merge_ms: u64,
+    // Sends all smoothed scrolling from a timer at this rate instead of with the input, 0 disables it
+    pub timer_output_hz: u32,
+    pub timer_output_catch_up_ms: u64,
+    // Uses one frame of the primary display as the burst merge window
+    pub match_refresh_rate: bool,
+    // How the app sends the scroll events it creates itself
+    pub output_backend: OutputBackend,
+    pub synthetic_target: ScrollTarget,
+    // How long a sent scroll event is expected back in the grab callback, 0 never expires it
+    pub own_event_timeout_ms: u64,
+    // Applied when running inside a remote desktop session
+    pub remote_session_policy: RemoteSessionPolicy,
+    pub remote_session_profile: Option<String>,
+    // Used for every app while running on battery, or while plugged in
+    pub battery_profile: Option<String>,
+    pub ac_profile: Option<String>,
+    // Keys that record the wheel input to gesture_file, and replay it
+    pub record_hotkey: Option<String>,
+    pub replay_hotkey: Option<String>,
+    pub gesture_file: String,
+    pub gesture_format: EventLogFormat,
+    pub bypass_while_dragging: bool,
+    // Window classes of focused windows that are never smoothed
+    pub bypass_window_classes: Vec<String>,
+    pub warmup_events: u32,
+    pub warmup_action: WarmupAction,
+    pub on_uncertain: UncertainAction,
+    pub flush_dropped_after_ms: u64,
+    pub flush_min_distance: Lines,
+    #[serde(alias = "ratchet_min_events")]
+    pub flush_min_events: u32,
+    pub smoothing_velocity_floor: f32,
+    pub suppress_cross_axis: bool,
+    pub lock_dominant_axis_per_gesture: bool,
+    pub clamp_overshoot: bool,
+    pub boost_window_ms: u64,
+    pub boost_multiplier: f32,
+    pub boost_min_distance: Lines,
+    pub gain_curve: Vec<(f32, f32)>,
+    pub gears: Vec<(f32, f32)>,
+    // How far past a gear's speed, as a fraction, shifting waits
+    pub gear_hysteresis: f32,
+    pub reset_on_focus_change: bool,
+    // Starts over when monitors are plugged in or unplugged, or the resolution changes
+    pub reset_on_display_change: bool,
+    // Most distance per axis that is added up from dropped events
+    pub max_dropped_distance: Lines,
+    pub rounding_mode: RoundingMode,
+    pub velocity_lock_hotkey: Option<String>,
+    // Safety cap, a lock is released after this long
+    pub velocity_lock_max_secs: u64,
+    pub fling_threshold: Lines,
+    pub fling_curve: FlingCurve,
+    pub fling_duration_ms: u64,
+    // Overrides fling_duration_ms for one axis
+    pub fling_duration_x_ms: Option<u64>,
+    pub fling_duration_y_ms: Option<u64>,
+    // Key that logs everything for log_boost_secs
+    pub log_boost_hotkey: Option<String>,
+    pub log_boost_secs: u64,
+    pub max_lines_per_second_x: f32,
+    pub max_lines_per_second_y: f32,
+    // Most lines that one gesture can scroll, 0 leaves it unlimited
+    pub max_gesture_distance: Lines,
+    // CSV files for the wheel input and output, both have to be set
+    pub trace_raw_file: Option<String>,
+    pub trace_output_file: Option<String>,
+}
+
+// Unset fields fall back to the global smoothing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AxisConfig {
+    pub smoothing: Option<SmoothingAlgorithm>,
+    pub time_constant_ms: Option<f32>,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
-        Self { log_to_file: false }
+        Self {
+            log_to_file: false,
+            log_level: LogLevel::Info,
+            log_panics: false,
+            log_to_event_log: false,
+            smoothing: SmoothingAlgorithm::None,
+            time_constant_ms: numeric_defaults::time_constant_ms,
+            smoothness: numeric_defaults::smoothness,
+            soft_start_secs: numeric_defaults::soft_start_secs,
+            reversal_cushion_ms: numeric_defaults::reversal_cushion_ms,
+            reversal_policy: ReversalPolicy::AnyAxis,
+            sign_neutral_zone: numeric_defaults::sign_neutral_zone,
+            clamp_zero_crossing: true,
+            axis_x: None,
+            axis_y: None,
+            min_speed: numeric_defaults::min_speed,
+            always_pass_above: numeric_defaults::always_pass_above,
+            bypass_large_deltas: true,
+            inclusive_threshold: true,
+            scale_thresholds_by_multiplier: false,
+            high_res: false,
+            high_res_scale: numeric_defaults::high_res_scale,
+            enabled: None,
+            toggle_hotkey: None,
+            reversal_toggle_count: numeric_defaults::reversal_toggle_count,
+            reversal_toggle_window_ms: numeric_defaults::reversal_toggle_window_ms,
+            reversal_toggle_min_distance: numeric_defaults::reversal_toggle_min_distance,
+            notify_on_toggle: false,
+            zoom_modifier: Some("Ctrl".to_string()),
+            zoom_step: numeric_defaults::zoom_step,
+            drift_epsilon: numeric_defaults::drift_epsilon,
+            drift_window_ms: numeric_defaults::drift_window_ms,
+            duplicate_window_ms: numeric_defaults::duplicate_window_ms,
+            click_scroll_cooldown_ms: numeric_defaults::click_scroll_cooldown_ms,
+            wheel_watchdog_secs: numeric_defaults::wheel_watchdog_secs,
+            startup_delay_ms: numeric_defaults::startup_delay_ms,
+            smooth_axes: SmoothAxes::Both,
+            emit_notches: false,
+            max_notches_per_event: numeric_defaults::max_notches_per_event,
+            coalesce_output: false,
+            output_jitter_epsilon: numeric_defaults::output_jitter_epsilon,
+            force_integer_notches: false,
+            idle_reset_ms: numeric_defaults::idle_reset_ms,
+            passthrough_first_tick: false,
+            cold_start: ColdStart::FirstDelta,
+            emit_scroll_end: false,
+            announce_command: Vec::new(),
+            announce_every_lines: numeric_defaults::announce_every_lines,
+            announce_min_interval_ms: numeric_defaults::announce_min_interval_ms,
+            heartbeat_secs: numeric_defaults::heartbeat_secs,
+            history_size: numeric_defaults::history_size,
+            control_port: numeric_defaults::control_port,
+            notify_on_start: false,
+            invert_x: false,
+            invert_y: false,
+            profiles: Vec::new(),
+            burst_merge_ms: numeric_defaults::burst_merge_ms,
+            timer_output_hz: numeric_defaults::timer_output_hz,
+            timer_output_catch_up_ms: numeric_defaults::timer_output_catch_up_ms,
+            match_refresh_rate: false,
+            output_backend: OutputBackend::Simulate,
+            synthetic_target: ScrollTarget::Focus,
+            own_event_timeout_ms: numeric_defaults::own_event_timeout_ms,
+            remote_session_policy: RemoteSessionPolicy::Ignore,
+            remote_session_profile: None,
+            battery_profile: None,
+            ac_profile: None,
+            record_hotkey: None,
+            replay_hotkey: None,
+            gesture_file: "gesture.txt".to_string(),
+            gesture_format: EventLogFormat::Text,
+            bypass_while_dragging: false,
+            bypass_window_classes: Vec::new(),
+            warmup_events: numeric_defaults::warmup_events,
+            warmup_action: WarmupAction::Pass,
+            on_uncertain: UncertainAction::Pass,
+            flush_dropped_after_ms: numeric_defaults::flush_dropped_after_ms,
+            flush_min_distance: numeric_defaults::flush_min_distance,
+            flush_min_events: numeric_defaults::flush_min_events,
+            smoothing_velocity_floor: numeric_defaults::smoothing_velocity_floor,
+            suppress_cross_axis: false,
+            lock_dominant_axis_per_gesture: false,
+            clamp_overshoot: false,
+            boost_window_ms: numeric_defaults::boost_window_ms,
+            boost_multiplier: numeric_defaults::boost_multiplier,
+            boost_min_distance: numeric_defaults::boost_min_distance,
+            gain_curve: Vec::new(),
+            gears: Vec::new(),
+            gear_hysteresis: numeric_defaults::gear_hysteresis,
+            reset_on_focus_change: false,
+            reset_on_display_change: false,
+            max_dropped_distance: numeric_defaults::max_dropped_distance,
+            rounding_mode: RoundingMode::Nearest,
+            velocity_lock_hotkey: None,
+            velocity_lock_max_secs: numeric_defaults::velocity_lock_max_secs,
+            fling_threshold: numeric_defaults::fling_threshold,
+            fling_curve: FlingCurve::Exponential,
+            fling_duration_ms: numeric_defaults::fling_duration_ms,
+            fling_duration_x_ms: numeric_defaults::fling_duration_x_ms,
+            fling_duration_y_ms: numeric_defaults::fling_duration_y_ms,
+            log_boost_hotkey: None,
+            log_boost_secs: numeric_defaults::log_boost_secs,
+            max_lines_per_second_x: numeric_defaults::max_lines_per_second_x,
+            max_lines_per_second_y: numeric_defaults::max_lines_per_second_y,
+            max_gesture_distance: numeric_defaults::max_gesture_distance,
+            trace_raw_file: None,
+            trace_output_file: None,
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for setting in NUMERIC_SETTINGS {
+            if let Some(value) = (setting.value)(self) {
+                check_range(setting.name, value, &setting.range)?;
+            }
+        }
+        for (name, axis) in [("axis_x", &self.axis_x), ("axis_y", &self.axis_y)] {
+            if let Some(time_constant_ms) = axis.as_ref().and_then(|axis| axis.time_constant_ms) {
+                check_range(
+                    &format!("{}.time_constant_ms", name),
+                    time_constant_ms as f64,
+                    &TIME_CONSTANT_RANGE_MS,
+                )?;
+            }
+        }
+        for (name, points) in [("gain_curve", &self.gain_curve), ("gears", &self.gears)] {
+            for (index, &(speed, multiplier)) in points.iter().enumerate() {
+                check_range(&format!("{} speed", name), speed as f64, &SPEED_RANGE)?;
+                check_range(
+                    &format!("{} multiplier", name),
+                    multiplier as f64,
+                    &MULTIPLIER_RANGE,
+                )?;
+                if index > 0 && speed < points[index - 1].0 {
+                    return Err(ConfigError::Validation(format!(
+                        "{} points must be sorted by speed",
+                        name
+                    )));
+                }
+            }
+        }
+        if self.burst_merge_ms > 0 && self.timer_output_hz > 0 {
+            return Err(ConfigError::Validation(
+                "burst_merge_ms and timer_output_hz can't be used together".to_string(),
+            ));
+        }
+        if !self.gain_curve.is_empty() && !self.gears.is_empty() {
+            return Err(ConfigError::Validation(
+                "gain_curve and gears can't be used together".to_string(),
+            ));
+        }
+        for profile in &self.profiles {
+            for (setting, value, max) in [
+                ("speed_multiplier", profile.speed_multiplier, MAX_MULTIPLIER),
+                ("lines_per_key", profile.lines_per_key, MAX_LINES),
+            ] {
+                let Some(value) = value else {
+                    continue;
+                };
+                if !(value > 0.0 && value as f64 <= max) {
+                    return Err(ConfigError::Validation(format!(
+                        "{} of profile {:?} must be above 0 and at most {}, got {}",
+                        setting, profile.name, max, value
+                    )));
+                }
+            }
+        }
+        let hotkeys = [
+            ("toggle_hotkey", &self.toggle_hotkey),
+            ("record_hotkey", &self.record_hotkey),
+            ("replay_hotkey", &self.replay_hotkey),
+            ("velocity_lock_hotkey", &self.velocity_lock_hotkey),
+            ("log_boost_hotkey", &self.log_boost_hotkey),
+        ];
+        for (setting, name) in hotkeys {
+            if let Some(name) = name {
+                if parse_key(name).is_none() {
+                    return Err(ConfigError::Validation(format!(
+                        "unknown {} {:?}",
+                        setting, name
+                    )));
+                }
+            }
+        }
+        if self.trace_raw_file.is_some() != self.trace_output_file.is_some() {
+            return Err(ConfigError::Validation(
+                "trace_raw_file and trace_output_file have to be set together".to_string(),
+            ));
+        }
+        if self.remote_session_policy == RemoteSessionPolicy::Profile {
+            let name = self.remote_session_profile.as_deref().unwrap_or_default();
+            if !self.profiles.iter().any(|profile| profile.name == name) {
+                return Err(ConfigError::Validation(format!(
+                    "remote_session_profile {:?} is not one of the profiles",
+                    name
+                )));
+            }
+        }
+        for (setting, name) in [
+            ("battery_profile", &self.battery_profile),
+            ("ac_profile", &self.ac_profile),
+        ] {
+            let Some(name) = name else {
+                continue;
+            };
+            if !self.profiles.iter().any(|profile| &profile.name == name) {
+                return Err(ConfigError::Validation(format!(
+                    "{} {:?} is not one of the profiles",
+                    setting, name
+                )));
+            }
+            if self.remote_session_policy == RemoteSessionPolicy::Profile {
+                return Err(ConfigError::Validation(format!(
+                    "{} can't be combined with the remote_session_policy \"profile\"",
+                    setting
+                )));
+            }
+        }
+        if let Some(name) = &self.zoom_modifier {
+            if parse_modifier(name).is_none() {
+                return Err(ConfigError::Validation(format!(
+                    "unknown zoom_modifier {:?}",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Turning up the smoothness turns on the smoothing as well, so that it works on its own
+    pub fn smoothing_algorithm(&self) -> SmoothingAlgorithm {
+        match (self.smoothing, self.smoothness) {
+            (SmoothingAlgorithm::None, Some(smoothness)) if smoothness > 0.0 => {
+                SmoothingAlgorithm::Exponential
+            }
+            (smoothing, _) => smoothing,
+        }
+    }
+
+    // The smoothness grows quadratically into the time constant, which gives finer control at the light end
+    pub fn time_constant(&self) -> Duration {
+        let time_constant_ms = match self.smoothness {
+            Some(smoothness) => MAX_SMOOTHNESS_TIME_CONSTANT_MS * (smoothness / 100.0).powi(2),
+            None => self.time_constant_ms,
+        };
+        Duration::from_secs_f32(time_constant_ms.max(0.0) / 1000.0)
+    }
+
+    // Without a smoothness, so that it doesn't override the time constant. An algorithm that
+    // only the smoothness turned on stays on.
+    pub fn set_time_constant_ms(&mut self, time_constant_ms: f32) {
+        self.smoothing = self.smoothing_algorithm();
+        self.smoothness = None;
+        self.time_constant_ms = time_constant_ms;
+    }
+
+    // A high resolution wheel sends many small steps instead of one notch, which would
+    // otherwise look like noise or too slow scrolling
+    pub fn threshold_scale(&self) -> f32 {
+        if self.high_res {
+            1.0 / self.high_res_scale
+        } else {
+            1.0
+        }
+    }
+
+    // The settings that can be tuned at runtime, in the units the handler uses
+    pub fn tuning(&self) -> Tuning {
+        Tuning {
+            time_constant: self.time_constant(),
+            min_speed: self.min_speed * self.threshold_scale(),
+            always_pass_above: Lines(self.always_pass_above.0 * self.threshold_scale()),
+        }
+    }
+
+    // None if both axes use the global smoothing
+    pub fn axis_smoothing(&self) -> Option<(AxisSmoothing, AxisSmoothing)> {
+        if self.axis_x.is_none() && self.axis_y.is_none() {
+            return None;
+        }
+        let resolve = |axis: &Option<AxisConfig>| {
+            let axis = axis.as_ref();
+            AxisSmoothing {
+                algorithm: axis
+                    .and_then(|axis| axis.smoothing)
+                    .unwrap_or_else(|| self.smoothing_algorithm()),
+                time_constant: axis
+                    .and_then(|axis| axis.time_constant_ms)
+                    .map(|time_constant_ms| Duration::from_secs_f32(time_constant_ms / 1000.0))
+                    .unwrap_or_else(|| self.time_constant()),
+            }
+        };
+        Some((resolve(&self.axis_x), resolve(&self.axis_y)))
     }
 }
 
-pub fn read_config() -> Result<AppConfig, Box<dyn std::error::Error>> {
-    let config_path = "config.json";
+impl From<&AppConfig> for EventHandlerConfig {
+    fn from(config: &AppConfig) -> Self {
+        // These names were already checked when validating the config
+        let toggle_key = config.toggle_hotkey.as_deref().and_then(parse_key);
+        let zoom_modifier = config.zoom_modifier.as_deref().and_then(parse_modifier);
+        let record_key = config.record_hotkey.as_deref().and_then(parse_key);
+        let replay_key = config.replay_hotkey.as_deref().and_then(parse_key);
+        let velocity_lock_key = config.velocity_lock_hotkey.as_deref().and_then(parse_key);
+        let log_boost_key = config.log_boost_hotkey.as_deref().and_then(parse_key);
+
+        let threshold_scale = config.threshold_scale();
+        let tuning = config.tuning();
+
+        let burst_merge_window = if config.match_refresh_rate && config.burst_merge_ms > 0 {
+            display::frame_time().unwrap_or(Duration::from_millis(config.burst_merge_ms))
+        } else {
+            Duration::from_millis(config.burst_merge_ms)
+        };
+
+        let timer_output_interval = match config.timer_output_hz {
+            0 => Duration::ZERO,
+            hz if config.match_refresh_rate => {
+                display::frame_time().unwrap_or(Duration::from_secs_f32(1.0 / hz as f32))
+            }
+            hz => Duration::from_secs_f32(1.0 / hz as f32),
+        };
 
+        // 1. Plot the scroll values
+        // 2. Plot the speed values
+        // 3. https://docs.google.com/spreadsheets/d/1irAZETTmwKNsD2Ho1e1_RrDXjAiplB_sUgW0JJKhyBM/edit#gid=0
+        // 4. Oh, so that's why the speed limiting works so well
+        EventHandlerConfig {
+            smoothing: config.smoothing_algorithm(),
+            time_constant: tuning.time_constant,
+            axis_smoothing: config.axis_smoothing(),
+            soft_start: Duration::from_secs_f32(config.soft_start_secs.max(0.0)),
+            reversal_cushion: Duration::from_millis(config.reversal_cushion_ms),
+            reversal_policy: config.reversal_policy,
+            sign_neutral_zone: Lines(config.sign_neutral_zone.0 * threshold_scale),
+            clamp_zero_crossing: config.clamp_zero_crossing,
+            min_speed: tuning.min_speed,
+            always_pass_above: tuning.always_pass_above,
+            bypass_large_deltas: config.bypass_large_deltas,
+            inclusive_threshold: config.inclusive_threshold,
+            scale_thresholds_by_multiplier: config.scale_thresholds_by_multiplier,
+            max_dropped_deltas: (config.max_dropped_distance, config.max_dropped_distance),
+            toggle_key,
+            reversal_toggle_count: config.reversal_toggle_count,
+            reversal_toggle_window: Duration::from_millis(config.reversal_toggle_window_ms),
+            reversal_toggle_min_distance: config.reversal_toggle_min_distance,
+            notify_on_toggle: config.notify_on_toggle,
+            zoom_modifier,
+            zoom_step: config.zoom_step,
+            drift_epsilon: Lines(config.drift_epsilon.0 * threshold_scale),
+            drift_window: Duration::from_millis(config.drift_window_ms),
+            duplicate_window: Duration::from_secs_f32(config.duplicate_window_ms / 1000.0),
+            click_scroll_cooldown: Duration::from_millis(config.click_scroll_cooldown_ms),
+            smooth_axes: config.smooth_axes,
+            emit_notches: config.emit_notches,
+            max_notches_per_event: config.max_notches_per_event,
+            coalesce_output: config.coalesce_output,
+            output_jitter_epsilon: config.output_jitter_epsilon,
+            force_integer_notches: config.force_integer_notches,
+            idle_reset: Duration::from_millis(config.idle_reset_ms),
+            passthrough_first_tick: config.passthrough_first_tick,
+            cold_start: config.cold_start,
+            emit_scroll_end: config.emit_scroll_end,
+            invert_x: config.invert_x,
+            invert_y: config.invert_y,
+            profiles: config.profiles.clone(),
+            burst_merge_window,
+            timer_output_interval,
+            timer_output_catch_up: Duration::from_millis(config.timer_output_catch_up_ms),
+            record_key,
+            replay_key,
+            gesture_path: PathBuf::from(&config.gesture_file),
+            gesture_format: config.gesture_format,
+            bypass_while_dragging: config.bypass_while_dragging,
+            bypass_window_classes: config.bypass_window_classes.clone(),
+            warmup_events: config.warmup_events,
+            warmup_action: config.warmup_action,
+            on_uncertain: config.on_uncertain,
+            flush_dropped_after: Duration::from_millis(config.flush_dropped_after_ms),
+            flush_min_distance: config.flush_min_distance,
+            flush_min_events: config.flush_min_events,
+            smoothing_velocity_floor: config.smoothing_velocity_floor * threshold_scale,
+            suppress_cross_axis: config.suppress_cross_axis,
+            lock_dominant_axis_per_gesture: config.lock_dominant_axis_per_gesture,
+            clamp_overshoot: config.clamp_overshoot,
+            boost_window: Duration::from_millis(config.boost_window_ms),
+            boost_multiplier: config.boost_multiplier,
+            boost_min_distance: Lines(config.boost_min_distance.0 * threshold_scale),
+            gain_curve: config.gain_curve.clone(),
+            gears: config.gears.clone(),
+            gear_hysteresis: config.gear_hysteresis,
+            history_size: config.history_size,
+            reset_on_focus_change: config.reset_on_focus_change,
+            reset_on_display_change: config.reset_on_display_change,
+            match_refresh_rate: config.match_refresh_rate,
+            rounding_mode: config.rounding_mode,
+            velocity_lock_key,
+            log_boost_key,
+            velocity_lock_max_duration: Duration::from_secs(config.velocity_lock_max_secs),
+            fling_threshold: config.fling_threshold,
+            fling_curve: config.fling_curve,
+            fling_duration: (
+                Duration::from_millis(
+                    config
+                        .fling_duration_x_ms
+                        .unwrap_or(config.fling_duration_ms),
+                ),
+                Duration::from_millis(
+                    config
+                        .fling_duration_y_ms
+                        .unwrap_or(config.fling_duration_ms),
+                ),
+            ),
+            max_lines_per_second: (config.max_lines_per_second_x, config.max_lines_per_second_y),
+            max_gesture_distance: config.max_gesture_distance,
+            announce_every_lines: if config.announce_command.is_empty() {
+                0.0
+            } else {
+                config.announce_every_lines
+            },
+            battery_profile: config.battery_profile.clone(),
+            ac_profile: config.ac_profile.clone(),
+        }
+    }
+}
+
+fn check_range(name: &str, value: f64, range: &RangeInclusive<f64>) -> Result<(), ConfigError> {
+    if range.contains(&value) {
+        return Ok(());
+    }
+    Err(ConfigError::Validation(format!(
+        "{} must be between {} and {}, got {}",
+        name,
+        range.start(),
+        range.end(),
+        value
+    )))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ConfigSource {
+    Flag,
+    Env,
+    Default,
+}
+
+// The --config flag wins over the environment variable, which wins over the default path
+pub fn resolve_config_path(flag_path: Option<&Path>) -> (PathBuf, ConfigSource) {
+    if let Some(path) = flag_path {
+        return (path.to_path_buf(), ConfigSource::Flag);
+    }
+    match std::env::var_os(CONFIG_ENV_VAR) {
+        Some(path) if !path.is_empty() => (PathBuf::from(path), ConfigSource::Env),
+        _ => (PathBuf::from(DEFAULT_CONFIG_PATH), ConfigSource::Default),
+    }
+}
+
+fn write_config_file(config_path: &Path, config: &AppConfig) -> Result<(), ConfigError> {
+    let config_json = serde_json::to_string_pretty(config)?;
+    let io_error = |source| ConfigError::Io {
+        path: config_path.to_path_buf(),
+        source,
+    };
+    let mut file = File::create(config_path).map_err(io_error)?;
+    file.write_all(config_json.as_bytes()).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    Ok(())
+}
+
+pub fn write_config(flag_path: Option<&Path>, config: &AppConfig) -> Result<(), ConfigError> {
+    let (config_path, _) = resolve_config_path(flag_path);
+    write_config_file(&config_path, config)?;
+    info!("Wrote config to {:?}", config_path);
+    Ok(())
+}
+
+// The default config, with the allowed values of each numeric setting as a comment behind it
+fn default_config_with_ranges() -> Result<String, ConfigError> {
+    let config_json = serde_json::to_string_pretty(&AppConfig::default())?;
+    let lines: Vec<String> = config_json
+        .lines()
+        .map(|line| {
+            // Nested settings are indented further, and never match
+            let setting = NUMERIC_SETTINGS
+                .iter()
+                .find(|setting| line.starts_with(&format!("  \"{}\":", setting.name)));
+            match setting {
+                Some(setting) => format!(
+                    "{} // {} to {}",
+                    line,
+                    setting.range.start(),
+                    setting.range.end()
+                ),
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+// Writes a new config with every setting at its default, and never overwrites an existing one
+pub fn init_config(flag_path: Option<&Path>) -> Result<PathBuf, ConfigError> {
+    let (config_path, _) = resolve_config_path(flag_path);
+    let io_error = |source| ConfigError::Io {
+        path: config_path.clone(),
+        source,
+    };
+    let config_json = default_config_with_ranges()?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&config_path)
+        .map_err(io_error)?;
+    file.write_all(config_json.as_bytes()).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    Ok(config_path)
+}
+
+// Removes the `//` comments that --init writes, which JSON itself doesn't allow.
+// Slashes inside of strings, like in paths or URLs, are kept.
+fn strip_comments(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    for line in text.lines() {
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut end = line.len();
+        for (index, character) in line.char_indices() {
+            match character {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '/' if !in_string && line[index + 1..].starts_with('/') => {
+                    end = index;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        stripped.push_str(&line[..end]);
+        stripped.push('\n');
+    }
+    stripped
+}
+
+// The parsed file, or None if it doesn't exist
+fn read_config_value(config_path: &Path) -> Result<Option<serde_json::Value>, ConfigError> {
+    let io_error = |source| ConfigError::Io {
+        path: config_path.to_path_buf(),
+        source,
+    };
     let mut file = match File::open(config_path) {
         Ok(file) => file,
-        Err(_) => {
-            let default_config = AppConfig::default();
-            let config_json = serde_json::to_string_pretty(&default_config)?;
-            let mut file = File::create(config_path)?;
-            file.write_all(config_json.as_bytes())?;
-            file.sync_all()?;
-            File::open(config_path)?
-        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(io_error(error)),
     };
 
     let mut config_content = String::new();
-    file.read_to_string(&mut config_content)?;
+    file.read_to_string(&mut config_content).map_err(io_error)?;
+    Ok(Some(serde_json::from_str(&strip_comments(
+        &config_content,
+    ))?))
+}
 
-    let config: AppConfig = serde_json::from_str(&config_content)?;
+// Objects are merged key by key, so that an override only changes what it sets.
+// Everything else, including lists like the profiles, is replaced as a whole.
+fn merge_config_values(base: &mut serde_json::Value, layer: serde_json::Value) {
+    match (base, layer) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_config_values(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+// The override file is layered on top of the base config, and may be missing on machines
+// without their own tweaks
+pub fn read_config(
+    flag_path: Option<&Path>,
+    override_path: Option<&Path>,
+) -> Result<AppConfig, ConfigError> {
+    let (config_path, source) = resolve_config_path(flag_path);
+    info!("Reading config from {:?} ({:?})", config_path, source);
+
+    let mut config_value = match read_config_value(&config_path)? {
+        Some(config_value) => config_value,
+        None => {
+            // Install locations like Program Files can be read-only, which shouldn't stop the app
+            if let Err(error) = write_config_file(&config_path, &AppConfig::default()) {
+                warn!(
+                    "Could not create a default config, using the defaults: {:?}",
+                    error
+                );
+            }
+            serde_json::Value::Object(Default::default())
+        }
+    };
+    if let Some(override_path) = override_path {
+        match read_config_value(override_path)? {
+            Some(override_value) => {
+                info!("Layering the config override from {:?}", override_path);
+                merge_config_values(&mut config_value, override_value);
+            }
+            None => info!("No config override at {:?}", override_path),
+        }
+    }
+
+    let config: AppConfig = serde_json::from_value(config_value)?;
+    config.validate()?;
 
     Ok(config)
 }
+
+// A broken config shouldn't keep the app from starting, so it falls back to the defaults
+pub fn read_config_or_default(flag_path: Option<&Path>, override_path: Option<&Path>) -> AppConfig {
+    match read_config(flag_path, override_path) {
+        Ok(config) => config,
+        Err(error) => {
+            warn!("Config rejected, using the defaults instead: {}", error);
+            // Without a console window, the log alone would go unnoticed
+            notify(
+                "Smoother scroll config rejected".to_string(),
+                format!("Using the default settings instead. {}", error),
+            );
+            AppConfig::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Durations from fractional milliseconds are rounded to whole nanoseconds
+    fn assert_close(actual: Duration, expected: Duration) {
+        let difference = actual.abs_diff(expected);
+        assert!(
+            difference < Duration::from_micros(1),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn handler_config_takes_the_smoothing_settings() {
+        let config = AppConfig {
+            time_constant_ms: 40.0,
+            min_speed: 0.02,
+            always_pass_above: Lines(2.5),
+            idle_reset_ms: 1234,
+            duplicate_window_ms: 1.5,
+            max_notches_per_event: 7,
+            invert_y: true,
+            ..AppConfig::default()
+        };
+        let handler_config = EventHandlerConfig::from(&config);
+        assert_close(handler_config.time_constant, Duration::from_millis(40));
+        assert_eq!(handler_config.min_speed, 0.02);
+        assert_eq!(handler_config.always_pass_above, Lines(2.5));
+        assert_eq!(handler_config.idle_reset, Duration::from_millis(1234));
+        assert_close(handler_config.duplicate_window, Duration::from_micros(1500));
+        assert_eq!(handler_config.max_notches_per_event, 7);
+        assert!(handler_config.invert_y);
+    }
+
+    #[test]
+    fn smoothness_overrides_the_time_constant() {
+        let config = AppConfig {
+            smoothing: SmoothingAlgorithm::None,
+            time_constant_ms: 40.0,
+            smoothness: Some(50.0),
+            ..AppConfig::default()
+        };
+        let handler_config = EventHandlerConfig::from(&config);
+        assert_eq!(handler_config.smoothing, SmoothingAlgorithm::Exponential);
+        assert_close(handler_config.time_constant, Duration::from_millis(50));
+    }
+
+    #[test]
+    fn high_res_wheels_scale_the_thresholds() {
+        let config = AppConfig {
+            high_res: true,
+            high_res_scale: 4.0,
+            min_speed: 0.02,
+            always_pass_above: Lines(2.0),
+            ..AppConfig::default()
+        };
+        let handler_config = EventHandlerConfig::from(&config);
+        assert_eq!(handler_config.min_speed, 0.005);
+        assert_eq!(handler_config.always_pass_above, Lines(0.5));
+    }
+
+    #[test]
+    fn defaults_are_within_their_ranges() {
+        assert!(AppConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_numbers_outside_of_their_ranges() {
+        let invalid_configs = [
+            // Would overflow the durations that are made from them
+            AppConfig {
+                time_constant_ms: 1e30,
+                ..AppConfig::default()
+            },
+            AppConfig {
+                soft_start_secs: 1e30,
+                ..AppConfig::default()
+            },
+            AppConfig {
+                duplicate_window_ms: 1e30,
+                ..AppConfig::default()
+            },
+            AppConfig {
+                axis_y: Some(AxisConfig {
+                    smoothing: None,
+                    time_constant_ms: Some(1e30),
+                }),
+                ..AppConfig::default()
+            },
+            // Would allocate the whole history up front
+            AppConfig {
+                history_size: usize::MAX,
+                ..AppConfig::default()
+            },
+            AppConfig {
+                max_notches_per_event: 0,
+                ..AppConfig::default()
+            },
+            AppConfig {
+                min_speed: f32::NAN,
+                ..AppConfig::default()
+            },
+            AppConfig {
+                fling_duration_x_ms: Some(u64::MAX),
+                ..AppConfig::default()
+            },
+        ];
+        for config in invalid_configs {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn strips_comments_outside_of_strings() {
+        let text = "{\n  \"min_speed\": 0.005, // 0 to 100\n  \"gesture_file\": \"C://a \\\"//\\\" b\" // ok\n}";
+        assert_eq!(
+            strip_comments(text),
+            "{\n  \"min_speed\": 0.005, \n  \"gesture_file\": \"C://a \\\"//\\\" b\" \n}\n"
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+// Runtime choices that should survive a restart.
+// Unlike the config, this file is written by the application itself.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppState {
+    pub enabled: bool,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+// Next to the config and named after it, so that instances with their own config keep their own state
+pub fn state_path(config_path: &Path) -> PathBuf {
+    let stem = config_path.file_stem().unwrap_or_default();
+    config_path.with_file_name(format!("{}.state.json", stem.to_string_lossy()))
+}
+
+fn write_state(path: &Path, state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let state_json = serde_json::to_string_pretty(state)?;
+    fs::write(path, state_json)?;
+    Ok(())
+}
+
+// Where the runtime state is kept. Saving happens on a thread of its own,
+// so that the grab callback never waits for the disk.
+#[derive(Clone)]
+pub struct StateStore {
+    path: PathBuf,
+    writer: mpsc::Sender<AppState>,
+}
+
+impl StateStore {
+    pub fn new(config_path: &Path) -> Self {
+        let path = state_path(config_path);
+        let (writer, receiver) = mpsc::channel::<AppState>();
+        {
+            let path = path.clone();
+            thread::spawn(move || {
+                while let Ok(state) = receiver.recv() {
+                    // Only the newest of several quick toggles has to be written
+                    let state = receiver.try_iter().last().unwrap_or(state);
+                    if let Err(error) = write_state(&path, &state) {
+                        error!("Failed to save state to {:?}: {:?}", path, error);
+                    }
+                }
+            });
+        }
+        StateStore { path, writer }
+    }
+
+    pub fn read(&self) -> AppState {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!("Ignoring invalid {:?}: {:?}", self.path, error);
+                AppState::default()
+            }),
+            Err(_) => AppState::default(),
+        }
+    }
+
+    pub fn save(&self, state: AppState) {
+        if self.writer.send(state).is_err() {
+            error!("State writer thread is gone, not saving the state");
+        }
+    }
+}
+
+// Flips smoothing on or off, and remembers the choice for the next launch if there is a store
+pub fn toggle_enabled(enabled: &AtomicBool, store: Option<&StateStore>) -> bool {
+    let enabled = !enabled.fetch_xor(true, Ordering::Relaxed);
+    info!("Smoothing enabled: {}", enabled);
+    if let Some(store) = store {
+        store.save(AppState { enabled });
+    }
+    enabled
+}
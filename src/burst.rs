@@ -0,0 +1,93 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rdev::{EventType, MouseScrollDelta};
+
+use crate::deadline::DeadlineTimer;
+use crate::emitter::Emitter;
+
+#[derive(Debug)]
+struct Burst {
+    delta_x: f32,
+    delta_y: f32,
+    started: Instant,
+}
+
+// Sums up quick successive scroll events in the same direction, and sends them as one event
+// at the end of the window. Fewer, larger events are easier on apps that get flooded by high polling rates.
+pub struct BurstMerger {
+    pending: Arc<Mutex<Option<Burst>>>,
+    window: Mutex<Duration>,
+    emitter: Emitter,
+    timer: DeadlineTimer,
+}
+
+impl BurstMerger {
+    pub fn new(window: Duration, emitter: Emitter) -> Self {
+        let pending = Arc::new(Mutex::new(None::<Burst>));
+        let timer = {
+            let pending = pending.clone();
+            let emitter = emitter.clone();
+            DeadlineTimer::new(move || {
+                let burst = pending.lock().unwrap().take();
+                if let Some(burst) = burst {
+                    emit(&emitter, &burst);
+                }
+                None
+            })
+        };
+        BurstMerger {
+            pending,
+            window: Mutex::new(window),
+            emitter,
+            timer,
+        }
+    }
+
+    // Takes effect for the burst that is pending, if any
+    pub fn set_window(&self, window: Duration) {
+        *self.window.lock().unwrap() = window;
+        let started = self
+            .pending
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|burst| burst.started);
+        if let Some(started) = started {
+            self.timer.set(started + window);
+        }
+    }
+
+    // Swallows the delta into the current burst. A change of direction sends the old burst right away.
+    pub fn add(&self, delta_x: f32, delta_y: f32) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(burst) = pending.as_mut() {
+            if same_direction(burst.delta_x, delta_x) && same_direction(burst.delta_y, delta_y) {
+                burst.delta_x += delta_x;
+                burst.delta_y += delta_y;
+                return;
+            }
+            emit(&self.emitter, burst);
+        }
+        let started = Instant::now();
+        *pending = Some(Burst {
+            delta_x,
+            delta_y,
+            started,
+        });
+        self.timer.set(started + *self.window.lock().unwrap());
+    }
+}
+
+fn same_direction(a: f32, b: f32) -> bool {
+    a == 0.0 || b == 0.0 || a.signum() == b.signum()
+}
+
+fn emit(emitter: &Emitter, burst: &Burst) {
+    emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+        burst.delta_x,
+        burst.delta_y,
+    )));
+}
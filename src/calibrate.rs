@@ -0,0 +1,322 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use rdev::{grab, Event, EventType, EventTypes, MouseScrollDelta};
+use tracing::{error, info};
+
+use crate::app_config::{resolve_config_path, write_config, AppConfig};
+use crate::event_log::{read_event_log, write_event_log, ScriptLine};
+use crate::notify::notify;
+use crate::units::Lines;
+
+const PHASE_DURATION: Duration = Duration::from_secs(5);
+// Saved next to the config, in the gesture format
+const RECORDING_FILE: &str = "calibration.txt";
+
+const PHASES: [&str; 4] = [
+    "Don't touch the wheel",
+    "Scroll slowly, as if reading line by line",
+    "Scroll at your usual pace",
+    "Scroll fast",
+];
+const REST_PHASE: usize = 0;
+const SLOW_PHASE: usize = 1;
+const USUAL_PHASE: usize = 2;
+
+// The smoothing should average over a few events of usual scrolling, without lagging behind it
+const TIME_CONSTANT_EVENTS: f32 = 3.0;
+const TIME_CONSTANT_RANGE_MS: (f32, f32) = (5.0, 200.0);
+
+#[derive(Debug, Default)]
+struct PhaseStats {
+    events: usize,
+    // Absolute deltas, sorted
+    deltas: Vec<f32>,
+    // Lines per millisecond, computed like the event handler does. Sorted.
+    speeds: Vec<f32>,
+    // Milliseconds between events, sorted
+    intervals: Vec<f32>,
+}
+
+#[derive(Debug)]
+struct Suggestion {
+    min_speed: f32,
+    always_pass_above: Lines,
+    time_constant_ms: f32,
+}
+
+// The app has no console window in release builds, so every step is shown as a notification too
+fn prompt(message: String) {
+    info!("{}", message);
+    println!("{}", message);
+    notify("Smoother scroll calibration".to_string(), message);
+}
+
+// Records the wheel while the user scrolls at different speeds, and suggests settings from it.
+// The recording is saved as an event log, which `recording` can analyse again later on without
+// grabbing the mouse. Recording never returns, the process exits once the last phase is done.
+pub fn run(
+    config: AppConfig,
+    config_path: Option<PathBuf>,
+    recording: Option<PathBuf>,
+    write: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(recording) = recording {
+        info!("Calibrating from the recording {:?}", recording);
+        return finish(&read_event_log(&recording)?, config, config_path, write);
+    }
+
+    // Wheel events since the first phase started, or None before it did
+    let samples = Arc::new(Mutex::new(None::<(SystemTime, Vec<ScriptLine>)>));
+    let recording = Arc::new(AtomicBool::new(false));
+
+    {
+        let samples = samples.clone();
+        let recording = recording.clone();
+        thread::spawn(move || {
+            prompt(format!(
+                "Calibration starts in 3 seconds. Each step takes {:?}.",
+                PHASE_DURATION
+            ));
+            thread::sleep(Duration::from_secs(3));
+            *samples.lock().unwrap() = Some((SystemTime::now(), Vec::new()));
+            recording.store(true, Ordering::Relaxed);
+            for (phase, instruction) in PHASES.iter().enumerate() {
+                prompt(format!(
+                    "Step {}/{}: {}",
+                    phase + 1,
+                    PHASES.len(),
+                    instruction
+                ));
+                thread::sleep(PHASE_DURATION);
+            }
+            recording.store(false, Ordering::Relaxed);
+
+            let samples = samples
+                .lock()
+                .unwrap()
+                .take()
+                .map(|(_, lines)| lines)
+                .unwrap_or_default();
+            let exit_code = match save_and_finish(&samples, config, config_path, write) {
+                Ok(()) => 0,
+                Err(error) => {
+                    prompt(format!("Calibration failed: {:?}", error));
+                    1
+                }
+            };
+            std::process::exit(exit_code);
+        });
+    }
+
+    let callback = move |event: Event| {
+        if let EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)) = event.event_type {
+            if recording.load(Ordering::Relaxed) {
+                if let Some((start_time, lines)) = samples.lock().unwrap().as_mut() {
+                    let millis = event
+                        .time
+                        .duration_since(*start_time)
+                        .unwrap_or_default()
+                        .as_millis();
+                    lines.push((millis as u64, delta_x, delta_y));
+                }
+            }
+        }
+        Some(event)
+    };
+    if let Err(error) = grab(
+        EventTypes {
+            keyboard: false,
+            mouse: true,
+        },
+        callback,
+    ) {
+        error!("Error: {:?}", error);
+    }
+
+    Ok(())
+}
+
+fn save_and_finish(
+    samples: &[ScriptLine],
+    config: AppConfig,
+    config_path: Option<PathBuf>,
+    write: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (resolved_path, _) = resolve_config_path(config_path.as_deref());
+    let recording_path = resolved_path.with_file_name(RECORDING_FILE);
+    match write_event_log(&recording_path, samples, config.gesture_format) {
+        Ok(()) => info!(
+            "Saved the calibration recording to {:?}, run with --calibrate --script {:?} to analyse it again",
+            recording_path, recording_path
+        ),
+        Err(error) => error!("Failed to save the calibration recording: {:?}", error),
+    }
+    finish(samples, config, config_path, write)
+}
+
+fn finish(
+    samples: &[ScriptLine],
+    mut config: AppConfig,
+    config_path: Option<PathBuf>,
+    write: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stats: Vec<PhaseStats> = (0..PHASES.len())
+        .map(|phase| phase_stats(samples, phase))
+        .collect();
+    for (phase, stats) in stats.iter().enumerate() {
+        info!(
+            "Step {}: {} events, {:.1} events per second, median delta {:?}, median speed {:?}",
+            phase + 1,
+            stats.events,
+            stats.events as f32 / PHASE_DURATION.as_secs_f32(),
+            percentile(&stats.deltas, 0.5),
+            percentile(&stats.speeds, 0.5)
+        );
+    }
+
+    let suggestion = suggest(&stats, &config);
+    prompt(format!(
+        "Suggested config: \"min_speed\": {}, \"always_pass_above\": {}, \"time_constant_ms\": {}",
+        suggestion.min_speed, suggestion.always_pass_above.0, suggestion.time_constant_ms
+    ));
+
+    if write {
+        config.min_speed = suggestion.min_speed;
+        config.always_pass_above = suggestion.always_pass_above;
+        config.set_time_constant_ms(suggestion.time_constant_ms);
+        write_config(config_path.as_deref(), &config)?;
+    } else {
+        info!("Run with --write-config to save these values");
+    }
+    Ok(())
+}
+
+// Each phase takes PHASE_DURATION, counted from the start of the recording
+fn phase_of(millis: u64) -> usize {
+    (millis / PHASE_DURATION.as_millis() as u64) as usize
+}
+
+fn phase_stats(samples: &[ScriptLine], phase: usize) -> PhaseStats {
+    let mut stats = PhaseStats::default();
+    let mut last_millis = None;
+    for &(millis, delta_x, delta_y) in samples
+        .iter()
+        .filter(|(millis, _, _)| phase_of(*millis) == phase)
+    {
+        let delta = delta_x.abs().max(delta_y.abs());
+        stats.events += 1;
+        stats.deltas.push(delta);
+        if let Some(last_millis) = last_millis {
+            let interval = millis.saturating_sub(last_millis) as f32;
+            if interval > 0.0 {
+                stats.speeds.push(delta / interval);
+                stats.intervals.push(interval);
+            }
+        }
+        last_millis = Some(millis);
+    }
+    stats.deltas.sort_by(f32::total_cmp);
+    stats.speeds.sort_by(f32::total_cmp);
+    stats.intervals.sort_by(f32::total_cmp);
+    stats
+}
+
+fn percentile(sorted_values: &[f32], p: f32) -> Option<f32> {
+    if sorted_values.is_empty() {
+        return None;
+    }
+    let index = ((sorted_values.len() - 1) as f32 * p).round() as usize;
+    Some(sorted_values[index])
+}
+
+// The noise floor is whatever the wheel reports while nobody touches it.
+// Thresholds go above that, but stay below the slowest intentional scrolling.
+// The time constant follows the event rate of usual scrolling.
+fn suggest(stats: &[PhaseStats], config: &AppConfig) -> Suggestion {
+    let rest = &stats[REST_PHASE];
+    let slow = &stats[SLOW_PHASE];
+    let usual = &stats[USUAL_PHASE];
+
+    let noise_speed = percentile(&rest.speeds, 0.95);
+    let slow_speed = percentile(&slow.speeds, 0.1);
+    let min_speed = match (noise_speed, slow_speed) {
+        (Some(noise), Some(slow)) if slow > noise => (noise + slow) / 2.0,
+        (Some(noise), _) => noise * 1.5,
+        (None, Some(slow)) => slow / 2.0,
+        (None, None) => config.min_speed,
+    };
+
+    let always_pass_above = match percentile(&rest.deltas, 1.0) {
+        Some(noise_delta) => Lines((noise_delta * 1.5).max(config.always_pass_above.0)),
+        None => config.always_pass_above,
+    };
+
+    let (min_time_constant, max_time_constant) = TIME_CONSTANT_RANGE_MS;
+    let time_constant_ms = match percentile(&usual.intervals, 0.5) {
+        Some(interval) => {
+            (interval * TIME_CONSTANT_EVENTS).clamp(min_time_constant, max_time_constant)
+        }
+        None => config.time_constant_ms,
+    };
+
+    Suggestion {
+        min_speed,
+        always_pass_above,
+        time_constant_ms,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `count` events of `delta`, `interval` milliseconds apart, starting at the beginning of `phase`
+    fn phase_samples(phase: usize, count: u64, interval: u64, delta: f32) -> Vec<ScriptLine> {
+        let start = phase as u64 * PHASE_DURATION.as_millis() as u64;
+        (0..count)
+            .map(|index| (start + index * interval, 0.0, delta))
+            .collect()
+    }
+
+    #[test]
+    fn suggests_settings_from_a_recording() {
+        let samples = [
+            phase_samples(REST_PHASE, 10, 400, 0.01),
+            phase_samples(SLOW_PHASE, 20, 200, 1.0),
+            phase_samples(USUAL_PHASE, 100, 20, 1.0),
+        ]
+        .concat();
+        let config = AppConfig::default();
+        let stats: Vec<PhaseStats> = (0..PHASES.len())
+            .map(|phase| phase_stats(&samples, phase))
+            .collect();
+        let suggestion = suggest(&stats, &config);
+
+        // Halfway between the noise at 0.01 / 400 and the slow scrolling at 1 / 200
+        assert!((suggestion.min_speed - (0.000025 + 0.005) / 2.0).abs() < 1e-6);
+        // The noise is small, so the default is kept
+        assert_eq!(suggestion.always_pass_above, config.always_pass_above);
+        assert_eq!(suggestion.time_constant_ms, 60.0);
+    }
+
+    #[test]
+    fn keeps_the_time_constant_without_usual_scrolling() {
+        let samples = phase_samples(REST_PHASE, 10, 400, 0.01);
+        let config = AppConfig::default();
+        let stats: Vec<PhaseStats> = (0..PHASES.len())
+            .map(|phase| phase_stats(&samples, phase))
+            .collect();
+        assert_eq!(
+            suggest(&stats, &config).time_constant_ms,
+            config.time_constant_ms
+        );
+    }
+}
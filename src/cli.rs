@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    pub config: Option<PathBuf>,
+    // Layered on top of the config, for settings that only apply to this machine
+    pub config_override: Option<PathBuf>,
+    pub calibrate: bool,
+    // Lets --calibrate save its suggestions
+    pub write_config: bool,
+    // Runs a scripted event stream instead of grabbing the mouse
+    pub script: Option<PathBuf>,
+    // Prints everything that is useful in a bug report, and exits
+    pub diagnose: bool,
+    // Writes a config with all the defaults, and exits
+    pub init: bool,
+}
+
+pub fn parse_args() -> Result<CliArgs, String> {
+    let mut cli_args = CliArgs::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args.next().ok_or("--config requires a path")?;
+                cli_args.config = Some(PathBuf::from(path));
+            }
+            "--config-override" => {
+                let path = args.next().ok_or("--config-override requires a path")?;
+                cli_args.config_override = Some(PathBuf::from(path));
+            }
+            "--script" => {
+                let path = args.next().ok_or("--script requires a path")?;
+                cli_args.script = Some(PathBuf::from(path));
+            }
+            "--calibrate" => cli_args.calibrate = true,
+            "--write-config" => cli_args.write_config = true,
+            "--diagnose" => cli_args.diagnose = true,
+            "--init" => cli_args.init = true,
+            _ => return Err(format!("Unknown argument {:?}", arg)),
+        }
+    }
+    Ok(cli_args)
+}
@@ -0,0 +1,47 @@
+use std::time::SystemTime;
+
+// Where the current time comes from, for the few places that can't use the time of an event.
+// The smoothing itself only looks at event timestamps, so scripted events control it either way.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(test)]
+pub use fake::FakeClock;
+
+#[cfg(test)]
+mod fake {
+    use std::{
+        sync::Mutex,
+        time::{Duration, SystemTime},
+    };
+
+    use super::Clock;
+
+    // Only moves when a test moves it
+    pub struct FakeClock(Mutex<SystemTime>);
+
+    impl FakeClock {
+        pub fn new(start: SystemTime) -> Self {
+            FakeClock(Mutex::new(start))
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+}
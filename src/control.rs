@@ -0,0 +1,310 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tracing::{error, info, warn};
+
+use crate::app_config::{write_config, AppConfig};
+use crate::event_handler::HandlerControls;
+use crate::units::Lines;
+
+// Requests are handled one after the other, so a client that connects and then stalls
+// must not keep the others waiting for long
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+// Browsers only send custom headers to another origin after asking first, which this server never
+// allows. Requiring one on changes keeps web pages from toggling the app with a form or fetch.
+const CONTROL_HEADER: &str = "X-Smoother-Scroll";
+
+// Everything the control server can look at and change
+pub struct ControlState {
+    pub controls: HandlerControls,
+    pub profile_names: Vec<String>,
+    // The config with the tuning applied, which is what gets saved
+    pub config: Mutex<AppConfig>,
+    // From --config, so that saving goes to the file that was read
+    pub config_path: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct StateResponse {
+    enabled: bool,
+    active_profile: Option<String>,
+    profile_override: Option<String>,
+}
+
+// In the units of the config file
+#[derive(Serialize)]
+struct TuningResponse {
+    time_constant_ms: f32,
+    min_speed: f32,
+    always_pass_above: f32,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+// A tiny HTTP server on localhost, for automation like Stream Deck buttons or window manager hooks.
+//
+// GET /state returns the current state as JSON.
+// POST /toggle turns smoothing on or off.
+// POST /profile/<name> forces a profile, and POST /profile goes back to picking it by the focused window.
+// GET /tuning returns the settings that can be tuned live, POST /tuning?<name>=<value>&... changes them,
+// and POST /tuning/save writes them to the config file.
+// POST requests need the CONTROL_HEADER.
+pub fn spawn_control_server(port: u16, state: ControlState) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(error) => {
+            error!(
+                "Failed to start the control server on port {}: {:?}",
+                port, error
+            );
+            return;
+        }
+    };
+    info!("Control server listening on http://127.0.0.1:{}", port);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(error) = handle_connection(stream, &state) {
+                        warn!("Control request failed: {:?}", error);
+                    }
+                }
+                Err(error) => warn!("Control connection failed: {:?}", error),
+            }
+        }
+    });
+}
+
+fn handle_connection(mut stream: TcpStream, state: &ControlState) -> io::Result<()> {
+    let started = Instant::now();
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_BYTES));
+    // The read timeout only limits each read, so a client could still trickle in byte by byte
+    let mut read_line = |line: &mut String| {
+        if started.elapsed() > REQUEST_TIMEOUT {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request took too long",
+            ));
+        }
+        reader.read_line(line)
+    };
+
+    let mut request_line = String::new();
+    read_line(&mut request_line)?;
+    // Besides the request line, only the control header matters
+    let mut has_control_header = false;
+    let mut header = String::new();
+    while read_line(&mut header)? > 0 && header.trim_end() != "" {
+        has_control_header |= is_control_header(&header);
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let (status, body) = if method == "POST" && !has_control_header {
+        (
+            "403 Forbidden",
+            error_json(&format!("POST requests need the {} header", CONTROL_HEADER)),
+        )
+    } else {
+        route(method, path, state)
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Header names aren't case sensitive, and any value will do
+fn is_control_header(header: &str) -> bool {
+    header
+        .split_once(':')
+        .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case(CONTROL_HEADER))
+}
+
+fn route(method: &str, path: &str, state: &ControlState) -> (&'static str, String) {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    match (method, path) {
+        ("GET", "/state") => ("200 OK", state_json(state)),
+        ("GET", "/tuning") => ("200 OK", tuning_json(state)),
+        ("POST", "/tuning") => {
+            let mut config = state.config.lock().unwrap();
+            let mut tuned = config.clone();
+            if let Err(message) = apply_tuning(&mut tuned, query) {
+                return ("400 Bad Request", error_json(&message));
+            }
+            state.controls.set_tuning(tuned.tuning());
+            *config = tuned;
+            drop(config);
+            ("200 OK", tuning_json(state))
+        }
+        ("POST", "/tuning/save") => {
+            let config = state.config.lock().unwrap();
+            match write_config(state.config_path.as_deref(), &config) {
+                Ok(()) => {
+                    drop(config);
+                    ("200 OK", tuning_json(state))
+                }
+                Err(error) => ("500 Internal Server Error", error_json(&error.to_string())),
+            }
+        }
+        ("POST", "/toggle") => {
+            state.controls.toggle_enabled();
+            ("200 OK", state_json(state))
+        }
+        ("POST", "/profile") => {
+            info!("Profile override cleared");
+            state.controls.set_profile_override(None);
+            ("200 OK", state_json(state))
+        }
+        ("POST", path) if path.starts_with("/profile/") => {
+            let name = percent_decode(&path["/profile/".len()..]);
+            if !state.profile_names.contains(&name) {
+                return (
+                    "404 Not Found",
+                    error_json(&format!("Unknown profile {:?}", name)),
+                );
+            }
+            info!("Profile override: {:?}", name);
+            state.controls.set_profile_override(Some(name));
+            ("200 OK", state_json(state))
+        }
+        (_, "/state" | "/toggle" | "/profile" | "/tuning" | "/tuning/save") => {
+            ("405 Method Not Allowed", error_json("Method not allowed"))
+        }
+        _ => ("404 Not Found", error_json("Not found")),
+    }
+}
+
+fn state_json(state: &ControlState) -> String {
+    let response = StateResponse {
+        enabled: state.controls.is_enabled(),
+        active_profile: state.controls.active_profile(),
+        profile_override: state.controls.profile_override(),
+    };
+    serde_json::to_string(&response).unwrap_or_default()
+}
+
+// Changes the settings from a query like `min_speed=0.01&time_constant_ms=20`, and checks them
+// like a config file. Nothing is changed if any of them is rejected.
+fn apply_tuning(config: &mut AppConfig, query: &str) -> Result<(), String> {
+    let mut tuned = config.clone();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("No value for {:?}", pair))?;
+        let value: f32 = value
+            .parse()
+            .map_err(|_| format!("Invalid {} {:?}", name, value))?;
+        match name {
+            "time_constant_ms" => tuned.set_time_constant_ms(value),
+            "min_speed" => tuned.min_speed = value,
+            "always_pass_above" => tuned.always_pass_above = Lines(value),
+            _ => return Err(format!("{:?} can't be tuned", name)),
+        }
+    }
+    tuned.validate().map_err(|error| error.to_string())?;
+    *config = tuned;
+    Ok(())
+}
+
+fn tuning_json(state: &ControlState) -> String {
+    let config = state.config.lock().unwrap();
+    let response = TuningResponse {
+        time_constant_ms: config.time_constant().as_secs_f32() * 1000.0,
+        min_speed: config.min_speed,
+        always_pass_above: config.always_pass_above.0,
+    };
+    serde_json::to_string(&response).unwrap_or_default()
+}
+
+fn error_json(message: &str) -> String {
+    let response = ErrorResponse {
+        error: message.to_string(),
+    };
+    serde_json::to_string(&response).unwrap_or_default()
+}
+
+// Profile names can contain spaces, which clients send as %20
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_the_control_header() {
+        assert!(is_control_header("X-Smoother-Scroll: 1\r\n"));
+        assert!(is_control_header("x-smoother-scroll:\r\n"));
+        assert!(!is_control_header("Origin: https://example.com\r\n"));
+        assert!(!is_control_header("X-Smoother-Scroll-Not\r\n"));
+    }
+
+    #[test]
+    fn tunes_the_config() {
+        let mut config = AppConfig {
+            smoothness: Some(50.0),
+            ..AppConfig::default()
+        };
+        apply_tuning(
+            &mut config,
+            "time_constant_ms=20&min_speed=0.01&always_pass_above=0.5",
+        )
+        .unwrap();
+        assert_eq!(config.time_constant_ms, 20.0);
+        // The smoothness would have overridden the new time constant
+        assert_eq!(config.smoothness, None);
+        assert_eq!(config.min_speed, 0.01);
+        assert_eq!(config.always_pass_above, Lines(0.5));
+    }
+
+    #[test]
+    fn rejects_invalid_tuning_as_a_whole() {
+        let mut config = AppConfig::default();
+        let min_speed = config.min_speed;
+        assert!(apply_tuning(&mut config, "min_speed=0.5&always_pass_above=-1").is_err());
+        assert!(apply_tuning(&mut config, "min_speed=fast").is_err());
+        assert!(apply_tuning(&mut config, "history_size=10").is_err());
+        assert_eq!(config.min_speed, min_speed);
+    }
+}
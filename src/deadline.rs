@@ -0,0 +1,114 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Instant,
+};
+
+// Calls `fire` on its own thread once the deadline has passed. The deadline can be moved at any
+// time, and while none is set, the thread blocks without a timer. `fire` can return the next
+// deadline to keep going, unless a new one was set in the meantime.
+pub struct DeadlineTimer {
+    deadline: Arc<Mutex<Option<Instant>>>,
+    wake: mpsc::Sender<()>,
+}
+
+impl DeadlineTimer {
+    pub fn new(mut fire: impl FnMut() -> Option<Instant> + Send + 'static) -> Self {
+        let deadline = Arc::new(Mutex::new(None::<Instant>));
+        let (wake, receiver) = mpsc::channel();
+        {
+            let deadline = deadline.clone();
+            thread::spawn(move || loop {
+                let next = *deadline.lock().unwrap();
+                let next = match next {
+                    Some(next) => next,
+                    None => {
+                        if receiver.recv().is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                };
+
+                let now = Instant::now();
+                if now < next {
+                    if let Err(mpsc::RecvTimeoutError::Disconnected) =
+                        receiver.recv_timeout(next - now)
+                    {
+                        return;
+                    }
+                    continue;
+                }
+                {
+                    let mut deadline = deadline.lock().unwrap();
+                    // Moved while this thread was looking at the old one
+                    if *deadline != Some(next) {
+                        continue;
+                    }
+                    *deadline = None;
+                }
+                if let Some(again) = fire() {
+                    deadline.lock().unwrap().get_or_insert(again);
+                }
+            });
+        }
+        DeadlineTimer { deadline, wake }
+    }
+
+    // Replaces the deadline, or sets it again after it has fired
+    pub fn set(&self, deadline: Instant) {
+        *self.deadline.lock().unwrap() = Some(deadline);
+        let _ = self.wake.send(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn fires_once_the_deadline_has_passed() {
+        let (fired, receiver) = mpsc::channel();
+        let timer = DeadlineTimer::new(move || {
+            fired.send(Instant::now()).unwrap();
+            None
+        });
+        let deadline = Instant::now() + Duration::from_millis(20);
+        timer.set(deadline);
+        assert!(receiver.recv_timeout(WAIT).unwrap() >= deadline);
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn a_moved_deadline_fires_at_the_new_time_only() {
+        let (fired, receiver) = mpsc::channel();
+        let timer = DeadlineTimer::new(move || {
+            fired.send(Instant::now()).unwrap();
+            None
+        });
+        timer.set(Instant::now() + Duration::from_millis(20));
+        let deadline = Instant::now() + Duration::from_millis(60);
+        timer.set(deadline);
+        assert!(receiver.recv_timeout(WAIT).unwrap() >= deadline);
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn keeps_going_with_the_deadline_it_returns() {
+        let (fired, receiver) = mpsc::channel();
+        let mut left = 2;
+        let timer = DeadlineTimer::new(move || {
+            fired.send(()).unwrap();
+            left -= 1;
+            (left > 0).then(|| Instant::now() + Duration::from_millis(10))
+        });
+        timer.set(Instant::now());
+        receiver.recv_timeout(WAIT).unwrap();
+        receiver.recv_timeout(WAIT).unwrap();
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fs, path::Path};
+
+use rdev::{simulate, EventType, MouseScrollDelta};
+use tracing::info;
+
+use crate::app_config::resolve_config_path;
+use crate::display;
+use crate::event_handler::EventHandlerConfig;
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETWHEELSCROLLCHARS, SPI_GETWHEELSCROLLLINES,
+    };
+
+    fn system_parameter(action: u32) -> Option<u32> {
+        let mut value = 0u32;
+        let success =
+            unsafe { SystemParametersInfoW(action, 0, &mut value as *mut u32 as *mut _, 0) };
+        (success != 0).then_some(value)
+    }
+
+    // Vertical lines and horizontal characters per notch
+    pub fn scroll_settings() -> Option<(u32, u32)> {
+        Some((
+            system_parameter(SPI_GETWHEELSCROLLLINES)?,
+            system_parameter(SPI_GETWHEELSCROLLCHARS)?,
+        ))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub fn scroll_settings() -> Option<(u32, u32)> {
+        None
+    }
+}
+
+// Everything that is useful in a bug report, printed in one go
+pub fn run(
+    flag_path: Option<&Path>,
+    override_path: Option<&Path>,
+    handler_config: &EventHandlerConfig,
+) {
+    let mut report = Vec::new();
+    report.push(format!(
+        "master-3-smoother-scroll {}",
+        env!("CARGO_PKG_VERSION")
+    ));
+    // Set by the build script, from the lock file
+    report.push(format!("rdev {}", env!("RDEV_VERSION")));
+    report.push(format!(
+        "OS: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    ));
+
+    let (config_path, source) = resolve_config_path(flag_path);
+    report.push(format!("Config file: {:?} ({:?})", config_path, source));
+    match fs::read_to_string(&config_path) {
+        Ok(content) => report.push(format!("Config file content:\n{}", content)),
+        Err(error) => report.push(format!("Config file unreadable: {:?}", error)),
+    }
+    if let Some(override_path) = override_path {
+        report.push(format!("Config override: {:?}", override_path));
+        match fs::read_to_string(override_path) {
+            Ok(content) => report.push(format!("Config override content:\n{}", content)),
+            Err(error) => report.push(format!("Config override unreadable: {:?}", error)),
+        }
+    }
+    report.push(format!("Active settings: {:#?}", handler_config));
+
+    match platform::scroll_settings() {
+        Some((lines, chars)) => report.push(format!(
+            "OS scroll settings: {} lines and {} characters per notch",
+            lines, chars
+        )),
+        None => report.push("OS scroll settings: unknown".to_string()),
+    }
+    match display::refresh_rate() {
+        Some(refresh_rate) => report.push(format!("Display refresh rate: {} Hz", refresh_rate)),
+        None => report.push("Display refresh rate: unknown".to_string()),
+    }
+
+    // A zero scroll doesn't move anything, but still goes through the whole injection path
+    match simulate(&EventType::Wheel(MouseScrollDelta::LineDelta(0.0, 0.0))) {
+        Ok(()) => report.push("Synthetic input: works".to_string()),
+        Err(error) => report.push(format!("Synthetic input: fails with {:?}", error)),
+    }
+
+    let report = report.join("\n");
+    info!("Diagnostics:\n{}", report);
+    println!("{}", report);
+}
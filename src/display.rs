@@ -0,0 +1,123 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, warn};
+
+// Docking or changing the resolution is rare, checking once a second while scrolling is plenty
+const LAYOUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Everything about the displays that changes when docking, undocking or changing the resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayLayout {
+    pub monitors: i32,
+    // Size of the area that spans all monitors
+    pub width: i32,
+    pub height: i32,
+    // Mode of the primary display
+    pub primary_width: u32,
+    pub primary_height: u32,
+    pub refresh_rate: Option<u32>,
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::mem;
+
+    use windows_sys::Win32::Graphics::Gdi::{
+        EnumDisplaySettingsW, DEVMODEW, ENUM_CURRENT_SETTINGS,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetSystemMetrics, SM_CMONITORS, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
+    };
+
+    use super::DisplayLayout;
+
+    fn primary_mode() -> Option<DEVMODEW> {
+        let mut mode: DEVMODEW = unsafe { mem::zeroed() };
+        mode.dmSize = mem::size_of::<DEVMODEW>() as u16;
+        let success =
+            unsafe { EnumDisplaySettingsW(std::ptr::null(), ENUM_CURRENT_SETTINGS, &mut mode) };
+        (success != 0).then_some(mode)
+    }
+
+    pub fn refresh_rate() -> Option<u32> {
+        let mode = primary_mode()?;
+        // 0 and 1 mean that the hardware default is used, which doesn't say anything
+        if mode.dmDisplayFrequency <= 1 {
+            return None;
+        }
+        Some(mode.dmDisplayFrequency)
+    }
+
+    pub fn layout() -> Option<DisplayLayout> {
+        let mode = primary_mode()?;
+        Some(DisplayLayout {
+            monitors: unsafe { GetSystemMetrics(SM_CMONITORS) },
+            width: unsafe { GetSystemMetrics(SM_CXVIRTUALSCREEN) },
+            height: unsafe { GetSystemMetrics(SM_CYVIRTUALSCREEN) },
+            primary_width: mode.dmPelsWidth,
+            primary_height: mode.dmPelsHeight,
+            refresh_rate: refresh_rate(),
+        })
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::DisplayLayout;
+
+    pub fn refresh_rate() -> Option<u32> {
+        None
+    }
+
+    pub fn layout() -> Option<DisplayLayout> {
+        None
+    }
+}
+
+// Refresh rate of the primary display in Hz, if the platform reports it
+pub fn refresh_rate() -> Option<u32> {
+    platform::refresh_rate()
+}
+
+// Merged and timed scroll events are sent once per frame, since the display can't show more than that
+pub fn frame_time() -> Option<Duration> {
+    let Some(refresh_rate) = refresh_rate() else {
+        warn!("Could not detect the display refresh rate");
+        return None;
+    };
+    let frame_time = Duration::from_secs_f32(1.0 / refresh_rate as f32);
+    info!(
+        "Display refresh rate: {} Hz, sending scroll events once per frame, every {:?}",
+        refresh_rate, frame_time
+    );
+    Some(frame_time)
+}
+
+// Notices when the display layout changes, by comparing it to the one at the last check
+#[derive(Default)]
+pub struct DisplayWatcher {
+    last_layout: Mutex<Option<(Instant, Option<DisplayLayout>)>>,
+}
+
+impl DisplayWatcher {
+    // The new layout, if it differs from the one at the last check. Within LAYOUT_CHECK_INTERVAL
+    // of the last check, this returns None without asking the OS again.
+    pub fn changed(&self) -> Option<DisplayLayout> {
+        let now = Instant::now();
+        let mut last_layout = self.last_layout.lock().unwrap();
+        if let Some((checked, _)) = *last_layout {
+            if now.duration_since(checked) < LAYOUT_CHECK_INTERVAL {
+                return None;
+            }
+        }
+        let layout = platform::layout();
+        let previous = last_layout.replace((now, layout));
+        match previous {
+            Some((_, previous)) if previous != layout => layout,
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::VecDeque,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use rdev::{simulate, EventType, MouseScrollDelta};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::clock::{Clock, SystemClock};
+use crate::trace::WheelTrace;
+use crate::window_message;
+
+// Simulated events come back through the grab callback, so the ones still in flight are remembered
+const MAX_IN_FLIGHT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputBackend {
+    // Injects the events into the system input queue
+    Simulate,
+    // Posts wheel messages straight to the focused window. They skip the input queue,
+    // which also means that they never come back through the grab callback.
+    WindowMessage,
+}
+
+// Which window the posted wheel messages go to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollTarget {
+    Focus,
+    // Some apps scroll the control under the cursor instead of the focused one
+    Cursor,
+}
+
+#[derive(Clone)]
+enum Sink {
+    Simulate(mpsc::Sender<EventType>),
+    WindowMessage(mpsc::Sender<EventType>),
+    // Collects the events instead of sending them to the OS, for headless runs
+    Buffer(Arc<Mutex<Vec<EventType>>>),
+    // Collects the events, but expects them back like simulated ones
+    #[cfg(test)]
+    Loopback(Arc<Mutex<Vec<EventType>>>),
+}
+
+// Sends synthetic events from a separate thread, since the grab callback must not block
+#[derive(Clone)]
+pub struct Emitter {
+    sink: Sink,
+    // When each event was sent, oldest first
+    in_flight: Arc<Mutex<VecDeque<(SystemTime, EventType)>>>,
+    // Events that didn't come back within this are forgotten, zero keeps them until pushed out
+    own_event_timeout: Duration,
+    clock: Arc<dyn Clock>,
+    trace: Option<Arc<WheelTrace>>,
+}
+
+impl Emitter {
+    pub fn new(backend: OutputBackend, target: ScrollTarget, own_event_timeout: Duration) -> Self {
+        let backend = if backend == OutputBackend::WindowMessage && !window_message::SUPPORTED {
+            warn!("Window messages aren't supported on this platform, simulating events instead");
+            OutputBackend::Simulate
+        } else {
+            backend
+        };
+
+        let (sender, receiver) = mpsc::channel::<EventType>();
+        thread::spawn(move || {
+            for event_type in receiver {
+                let result = match (backend, event_type) {
+                    (
+                        OutputBackend::WindowMessage,
+                        EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)),
+                    ) => window_message::send_wheel(delta_x, delta_y, target),
+                    _ => simulate(&event_type).map_err(|error| format!("{:?}", error)),
+                };
+                if let Err(error) = result {
+                    error!("Failed to send {:?}: {}", event_type, error);
+                }
+            }
+        });
+        let sink = match backend {
+            OutputBackend::Simulate => Sink::Simulate(sender),
+            OutputBackend::WindowMessage => Sink::WindowMessage(sender),
+        };
+        Emitter {
+            sink,
+            in_flight: Arc::new(Mutex::new(VecDeque::new())),
+            own_event_timeout,
+            clock: Arc::new(SystemClock),
+            trace: None,
+        }
+    }
+
+    pub fn buffered() -> (Self, Arc<Mutex<Vec<EventType>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let emitter = Emitter {
+            sink: Sink::Buffer(buffer.clone()),
+            in_flight: Arc::new(Mutex::new(VecDeque::new())),
+            own_event_timeout: Duration::ZERO,
+            clock: Arc::new(SystemClock),
+            trace: None,
+        };
+        (emitter, buffer)
+    }
+
+    // Like a simulating emitter, whose events a test can hand back to the grab callback
+    #[cfg(test)]
+    pub fn loopback(own_event_timeout: Duration) -> (Self, Arc<Mutex<Vec<EventType>>>) {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let emitter = Emitter {
+            sink: Sink::Loopback(buffer.clone()),
+            in_flight: Arc::new(Mutex::new(VecDeque::new())),
+            own_event_timeout,
+            clock: Arc::new(SystemClock),
+            trace: None,
+        };
+        (emitter, buffer)
+    }
+
+    // Used for the expiry of the events in flight
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Writes every wheel event that is sent to the output trace
+    pub fn with_trace(mut self, trace: Arc<WheelTrace>) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+
+    pub fn trace(&self) -> Option<&WheelTrace> {
+        self.trace.as_deref()
+    }
+
+    pub fn emit(&self, event_type: EventType) {
+        if let (Some(trace), EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y))) =
+            (&self.trace, event_type)
+        {
+            trace.output(delta_x, delta_y);
+        }
+        match &self.sink {
+            Sink::Simulate(sender) => {
+                self.remember(event_type);
+                if sender.send(event_type).is_err() {
+                    error!("Emitter thread is gone, dropping {:?}", event_type);
+                }
+            }
+            Sink::WindowMessage(sender) => {
+                if sender.send(event_type).is_err() {
+                    error!("Emitter thread is gone, dropping {:?}", event_type);
+                }
+            }
+            Sink::Buffer(buffer) => buffer.lock().unwrap().push(event_type),
+            #[cfg(test)]
+            Sink::Loopback(buffer) => {
+                self.remember(event_type);
+                buffer.lock().unwrap().push(event_type);
+            }
+        }
+    }
+
+    // Only scroll events are checked when they come back
+    fn remember(&self, event_type: EventType) {
+        if let EventType::Wheel(_) = event_type {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if in_flight.len() >= MAX_IN_FLIGHT {
+                in_flight.pop_front();
+            }
+            in_flight.push_back((self.clock.now(), event_type));
+        }
+    }
+
+    // Returns true if the event was sent by us, and forgets about it. An event that never came back
+    // would otherwise swallow the next real one with the same delta, so old ones expire first.
+    pub fn is_own_event(&self, event_type: &EventType) -> bool {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if !self.own_event_timeout.is_zero() {
+            let now = self.clock.now();
+            while let Some((sent, _)) = in_flight.front() {
+                if now.duration_since(*sent).unwrap_or_default() <= self.own_event_timeout {
+                    break;
+                }
+                in_flight.pop_front();
+            }
+        }
+        match in_flight.iter().position(|(_, sent)| sent == event_type) {
+            Some(index) => {
+                in_flight.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+
+    #[test]
+    fn forgets_own_events_that_never_came_back() {
+        let clock = Arc::new(FakeClock::new(SystemTime::UNIX_EPOCH));
+        let (emitter, emitted) = Emitter::loopback(Duration::from_millis(250));
+        let emitter = emitter.with_clock(clock.clone());
+        let event_type = EventType::Wheel(MouseScrollDelta::LineDelta(0.0, 1.0));
+
+        emitter.emit(event_type);
+        assert_eq!(*emitted.lock().unwrap(), vec![event_type]);
+        assert!(emitter.is_own_event(&event_type));
+        // Every sent event is only recognized once
+        assert!(!emitter.is_own_event(&event_type));
+
+        // A real scroll with the same delta is no longer taken for the lost one
+        emitter.emit(event_type);
+        clock.advance(Duration::from_millis(300));
+        assert!(!emitter.is_own_event(&event_type));
+    }
+}
@@ -0,0 +1,2356 @@
+use rdev::{Event, EventType, Key, MouseScrollDelta};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{self},
+};
+use tracing::{debug, info, warn};
+
+use crate::app_state::{toggle_enabled, StateStore};
+use crate::burst::BurstMerger;
+use crate::clock::Clock;
+use crate::display::DisplayWatcher;
+use crate::emitter::Emitter;
+use crate::event_log::EventLogFormat;
+use crate::filter::{
+    create_axis_filter, create_filter, AxisSmoothing, ReversalPolicy, SharedFilter,
+    SmoothingAlgorithm,
+};
+use crate::fling::{Fling, FlingCurve};
+use crate::flush::{DroppedDeltas, DroppedFlusher};
+use crate::foreground::ForegroundApp;
+use crate::gain::{gain, select_gear};
+use crate::gesture::GestureRecorder;
+use crate::history::{HistoryEntry, ScrollHistory};
+use crate::hotkey::{HeldKeys, Modifier};
+use crate::notify::notify;
+use crate::power::{PowerSource, PowerWatcher};
+use crate::profile::{find_profile, Profile, ScrollKeys};
+use crate::rate_limit::RateLimiter;
+use crate::reversal_toggle::ReversalToggle;
+use crate::scroll_end::ScrollEndNotifier;
+use crate::timer_output::TimerOutput;
+use crate::units::Lines;
+use crate::velocity_lock::VelocityLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmoothAxes {
+    Both,
+    Vertical,
+    Horizontal,
+}
+
+// What happens to the scroll events during the warm-up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmupAction {
+    Pass,
+    Drop,
+}
+
+// How the smoothing starts out on the first scroll event of a gesture, which has no time to
+// the previous one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColdStart {
+    // The smoothing starts at the first delta, so that it can't overshoot
+    FirstDelta,
+    // The smoothing starts at zero and ramps in
+    Zero,
+}
+
+// What happens to a scroll event that the smoothing can't make a sound decision about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UncertainAction {
+    Pass,
+    Drop,
+}
+
+// How deltas are rounded to whole steps. The rounded away part is carried over either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    Nearest,
+    TowardZero,
+    AwayFromZero,
+    // Nearest, with ties going to the even step
+    Bankers,
+}
+
+impl RoundingMode {
+    fn round(self, value: f32) -> f32 {
+        match self {
+            RoundingMode::Nearest => value.round(),
+            RoundingMode::TowardZero => value.trunc(),
+            RoundingMode::AwayFromZero => value.abs().ceil().copysign(value),
+            RoundingMode::Bankers => value.round_ties_even(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EventHandlerConfig {
+    pub smoothing: SmoothingAlgorithm,
+    pub time_constant: time::Duration,
+    // The time constant grows from zero to the configured one over this long after launch
+    pub soft_start: time::Duration,
+    // Fades out the old direction over this long after a reversal, zero switches right away
+    pub reversal_cushion: time::Duration,
+    // Which axes are reset when a diagonal scroll reverses on one of them
+    pub reversal_policy: ReversalPolicy,
+    // Deltas below this don't count as a change of direction
+    pub sign_neutral_zone: Lines,
+    // Stops the smoothed output at zero instead of letting it overshoot without a reversal
+    pub clamp_zero_crossing: bool,
+    // Separate smoothing for the x and the y axis, instead of the one above
+    pub axis_smoothing: Option<(AxisSmoothing, AxisSmoothing)>,
+    // Scroll speed in lines per millisecond
+    pub min_speed: f32,
+    pub always_pass_above: Lines,
+    // Without it, only the speed decides, no matter how large a delta is
+    pub bypass_large_deltas: bool,
+    // Whether a value exactly at min_speed or always_pass_above counts as reaching it
+    pub inclusive_threshold: bool,
+    // Multiplies min_speed and always_pass_above by the speed_multiplier of the active profile
+    pub scale_thresholds_by_multiplier: bool,
+    pub max_dropped_deltas: (Lines, Lines),
+    pub toggle_key: Option<Key>,
+    // Scrolling back and forth this many times within the window toggles smoothing, zero disables it
+    pub reversal_toggle_count: u32,
+    pub reversal_toggle_window: time::Duration,
+    // How far each stroke has to go to count
+    pub reversal_toggle_min_distance: Lines,
+    // Shows a notification when the reversal pattern toggles smoothing
+    pub notify_on_toggle: bool,
+    // While held, scrolling zooms in most apps and isn't smoothed
+    pub zoom_modifier: Option<Modifier>,
+    // Rounds zoom scrolling to multiples of this, zero sends it as it is
+    pub zoom_step: Lines,
+    // Events below this magnitude count as drift, 0.0 disables the detector
+    pub drift_epsilon: Lines,
+    // How long only drift has to be reported before scrolling is stopped
+    pub drift_window: time::Duration,
+    // Drops repeats of the same event that some drivers deliver, zero disables it
+    pub duplicate_window: time::Duration,
+    // Drops scrolling for this long after a mouse button press, zero disables it
+    pub click_scroll_cooldown: time::Duration,
+    pub smooth_axes: SmoothAxes,
+    // Turns the vertical output into whole line events
+    pub emit_notches: bool,
+    pub max_notches_per_event: u32,
+    // Sends the notches of one event as one event instead of one per line
+    pub coalesce_output: bool,
+    // Outputs closer than this to the last one repeat it, zero disables it
+    pub output_jitter_epsilon: Lines,
+    // Rounds the output to whole lines, unless the active profile says otherwise
+    pub force_integer_notches: bool,
+    // A scroll after a longer pause than this, for example after sleep and resume, starts from a clean state
+    pub idle_reset: time::Duration,
+    // Keeps the first tick of every gesture, for immediate feedback
+    pub passthrough_first_tick: bool,
+    pub cold_start: ColdStart,
+    // Sends a zero scroll event once the idle reset time has passed without scrolling
+    pub emit_scroll_end: bool,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    // Per app overrides, picked by the focused window
+    pub profiles: Vec<Profile>,
+    // Merges same direction events within this window into one, zero disables it
+    pub burst_merge_window: time::Duration,
+    // Time between timer output ticks, zero sends the output with the input events
+    pub timer_output_interval: time::Duration,
+    pub timer_output_catch_up: time::Duration,
+    // Starts and stops recording the wheel input to the gesture file
+    pub record_key: Option<Key>,
+    // Replays the gesture file through the smoothing
+    pub replay_key: Option<Key>,
+    pub gesture_path: PathBuf,
+    pub gesture_format: EventLogFormat,
+    // Skips the smoothing while the primary button is held, for example while dragging a scrollbar
+    pub bypass_while_dragging: bool,
+    // Skips the smoothing while a window of one of these classes has focus, compared case-insensitively
+    pub bypass_window_classes: Vec<String>,
+    // The first scroll events after launch skip the smoothing, while the driver settles
+    pub warmup_events: u32,
+    pub warmup_action: WarmupAction,
+    // Used for events with broken timestamps, broken smoothing state or non-finite output
+    pub on_uncertain: UncertainAction,
+    // Sends the dropped distance once nothing was scrolled for this long, zero disables it
+    pub flush_dropped_after: time::Duration,
+    pub flush_min_distance: Lines,
+    // Dropped events in one direction that have to add up before anything is sent
+    pub flush_min_events: u32,
+    // Scroll speed in lines per millisecond below which the smoothing is skipped
+    pub smoothing_velocity_floor: f32,
+    // Only sends output on the axes that were scrolled
+    pub suppress_cross_axis: bool,
+    // Ignores the other axis once a gesture has started on one
+    pub lock_dominant_axis_per_gesture: bool,
+    // Never lets the smoothed output exceed the raw delta in the same direction
+    pub clamp_overshoot: bool,
+    // Two ticks within this window after a pause are sent amplified, zero disables it
+    pub boost_window: time::Duration,
+    pub boost_multiplier: f32,
+    // How large an event has to be to count as a tick
+    pub boost_min_distance: Lines,
+    // Output multiplier by scroll speed, as `(lines per millisecond, multiplier)` points
+    pub gain_curve: Vec<(f32, f32)>,
+    // Discrete `(speed, multiplier)` steps instead of the gain curve
+    pub gears: Vec<(f32, f32)>,
+    pub gear_hysteresis: f32,
+    // Number of recent scroll events in the history
+    pub history_size: usize,
+    pub reset_on_focus_change: bool,
+    pub reset_on_display_change: bool,
+    // Follows the refresh rate of a new display with the burst merge window
+    pub match_refresh_rate: bool,
+    // Used by force_integer_notches and zoom_step
+    pub rounding_mode: RoundingMode,
+    // Keeps scrolling at the current speed until pressed again or the wheel moves
+    pub velocity_lock_key: Option<Key>,
+    pub velocity_lock_max_duration: time::Duration,
+    // Deltas at least this large coast out instead of being sent at once, zero disables it
+    pub fling_threshold: Lines,
+    // How a fling slows down, and how long it takes to stop on each axis
+    pub fling_curve: FlingCurve,
+    pub fling_duration: (time::Duration, time::Duration),
+    // Logs everything for a while, to capture a rare glitch
+    pub log_boost_key: Option<Key>,
+    // Most lines per second that are sent on each axis, zero leaves the axis unlimited
+    pub max_lines_per_second: (f32, f32),
+    // Most lines that one gesture sends, zero leaves it unlimited
+    pub max_gesture_distance: Lines,
+    // The line listener is called each time the gesture has scrolled this many more lines
+    pub announce_every_lines: f32,
+    // Profiles that are used for every app while on battery or plugged in
+    pub battery_profile: Option<String>,
+    pub ac_profile: Option<String>,
+}
+
+type ProfileListener = Box<dyn Fn(Option<&Profile>) + Send + Sync>;
+
+// The settings that can be changed while the app runs, through the control server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    pub time_constant: time::Duration,
+    pub min_speed: f32,
+    pub always_pass_above: Lines,
+}
+
+impl EventHandlerConfig {
+    // The values at launch
+    pub fn tuning(&self) -> Tuning {
+        Tuning {
+            time_constant: self.time_constant,
+            min_speed: self.min_speed,
+            always_pass_above: self.always_pass_above,
+        }
+    }
+}
+
+impl Tuning {
+    // The thresholds for deltas that were multiplied by `factor`
+    fn scaled(self, factor: f32) -> Tuning {
+        Tuning {
+            min_speed: self.min_speed * factor,
+            always_pass_above: Lines(self.always_pass_above.0 * factor),
+            ..self
+        }
+    }
+}
+
+// Called with the whole lines scrolled so far in the gesture
+type LineListener = Box<dyn Fn(i64) + Send + Sync>;
+
+// There is one smoothing state for all pointing devices. rdev doesn't report which device
+// an event came from, so two wheels that scroll at the same time share it.
+pub struct EventHandler {
+    // None before the first scroll event, and after an idle reset
+    last_scroll: Arc<Mutex<Option<ScrollWithTimestamp>>>,
+    filter: SharedFilter,
+    dropped_deltas: Arc<Mutex<DroppedDeltas>>,
+    drift: Arc<Mutex<DriftState>>,
+    // Vertical distance that hasn't added up to a whole notch yet
+    notch_remainder: Arc<Mutex<f32>>,
+    // Distance that was rounded away by force_integer_notches
+    integer_remainder: Arc<Mutex<(f32, f32)>>,
+    zoom_remainder: Arc<Mutex<(f32, f32)>>,
+    // Vertical distance that hasn't added up to a key press yet, in key presses
+    key_remainder: Mutex<f32>,
+    held_keys: HeldKeys,
+    emitter: Emitter,
+    // Where toggling saves the enabled flag, runs without one don't save it
+    state_store: Option<StateStore>,
+    foreground: ForegroundApp,
+    display: DisplayWatcher,
+    // Only there with battery_profile or ac_profile
+    power: Option<PowerWatcher>,
+    burst_merger: Option<BurstMerger>,
+    fling: Option<Fling>,
+    timer_output: Option<TimerOutput>,
+    reversal_toggle: Option<ReversalToggle>,
+    dropped_flusher: Option<DroppedFlusher>,
+    scroll_end: Option<ScrollEndNotifier>,
+    rate_limiter: Option<RateLimiter>,
+    active_profile: Arc<Mutex<Option<String>>>,
+    // The speed_multiplier of the profile that was active for the last scroll event
+    speed_multiplier: Mutex<f32>,
+    // Profile picked at runtime, which wins over the focused window
+    profile_override: Arc<Mutex<Option<String>>>,
+    // Called whenever the active profile changes
+    profile_listener: Option<ProfileListener>,
+    // Called when the log boost key is pressed
+    log_boost: Option<Box<dyn Fn() + Send + Sync>>,
+    line_listener: Option<LineListener>,
+    // Vertical lines sent in the current gesture, and how many stretches of them were announced
+    scrolled_lines: Mutex<(f32, i64)>,
+    gestures: GestureRecorder,
+    velocity_lock: VelocityLock,
+    // Smoothed speed of the last scroll event in lines per millisecond
+    last_velocity: Mutex<(f32, f32)>,
+    // Last wheel output, for the jitter suppression
+    last_output: Mutex<(f32, f32)>,
+    // Axis the current gesture is locked to
+    gesture_axis: Mutex<Option<Axis>>,
+    // Lines the current gesture has sent, for max_gesture_distance
+    gesture_distance: Mutex<f32>,
+    // Last wheel event that wasn't a duplicate
+    last_wheel: Mutex<Option<ScrollWithTimestamp>>,
+    // Last mouse button press whose cooldown hasn't been noticed to end yet
+    last_click: Mutex<Option<time::SystemTime>>,
+    // Index into the gears, None below the first one
+    gear: Mutex<Option<usize>>,
+    enabled: Arc<AtomicBool>,
+    wheel_event_seen: Arc<AtomicBool>,
+    // Scroll events left until the warm-up is over
+    warmup_remaining: AtomicU32,
+    counters: Arc<ScrollCounters>,
+    diagnostics: Arc<ScrollDiagnostics>,
+    history: Arc<ScrollHistory>,
+    // Ignores key repeats while the toggle key is held down
+    toggle_key_down: AtomicBool,
+    // Time between the previous scroll event and the one before it, or None if the previous one
+    // started from a clean state and had nothing to measure against
+    last_gap: Mutex<Option<time::Duration>>,
+    // Starts out from the config, and replaces its values once they are changed at runtime
+    tuning: Arc<Mutex<Tuning>>,
+    config: EventHandlerConfig,
+    // Launch time, for the soft start
+    start_time: time::SystemTime,
+}
+
+#[derive(Clone, Debug)]
+struct ScrollWithTimestamp {
+    delta_x: f32,
+    delta_y: f32,
+    timestamp: time::SystemTime,
+}
+
+// Scroll events that went through the smoothing, and how many of them were dropped
+#[derive(Debug, Default)]
+pub struct ScrollCounters {
+    pub processed: AtomicU64,
+    pub dropped: AtomicU64,
+}
+
+// The last scroll event that went through the smoothing, for sampling from other threads
+#[derive(Debug, Default)]
+pub struct ScrollDiagnostics {
+    raw: (AtomicF32, AtomicF32),
+    smoothed: (AtomicF32, AtomicF32),
+    alpha: AtomicF32,
+}
+
+impl ScrollDiagnostics {
+    pub fn raw_x(&self) -> f32 {
+        self.raw.0.load()
+    }
+
+    pub fn raw_y(&self) -> f32 {
+        self.raw.1.load()
+    }
+
+    pub fn smoothed_x(&self) -> f32 {
+        self.smoothed.0.load()
+    }
+
+    pub fn smoothed_y(&self) -> f32 {
+        self.smoothed.1.load()
+    }
+
+    pub fn last_alpha(&self) -> f32 {
+        self.alpha.load()
+    }
+}
+
+// The state that other threads, or tasks of an async runtime, can look at and change while
+// the handler runs. It is cheap to clone, and every lock is only held to copy a value, so
+// callers never wait on the input thread for long and never hold it up either.
+#[derive(Clone)]
+pub struct HandlerControls {
+    enabled: Arc<AtomicBool>,
+    state_store: Option<StateStore>,
+    active_profile: Arc<Mutex<Option<String>>>,
+    profile_override: Arc<Mutex<Option<String>>>,
+    tuning: Arc<Mutex<Tuning>>,
+    filter: SharedFilter,
+}
+
+impl HandlerControls {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    // Returns the new state
+    pub fn toggle_enabled(&self) -> bool {
+        toggle_enabled(&self.enabled, self.state_store.as_ref())
+    }
+
+    // Name of the profile that was used for the last scroll event
+    pub fn active_profile(&self) -> Option<String> {
+        self.active_profile.lock().unwrap().clone()
+    }
+
+    pub fn profile_override(&self) -> Option<String> {
+        self.profile_override.lock().unwrap().clone()
+    }
+
+    // None goes back to picking the profile by the focused window
+    pub fn set_profile_override(&self, name: Option<String>) {
+        *self.profile_override.lock().unwrap() = name;
+    }
+
+    // Takes effect with the next scroll event. The smoothing keeps its state, so that tuning
+    // while scrolling doesn't jump.
+    pub fn set_tuning(&self, tuning: Tuning) {
+        info!("Tuning changed to {:?}", tuning);
+        *self.tuning.lock().unwrap() = tuning;
+        self.filter
+            .lock()
+            .unwrap()
+            .set_time_constant(tuning.time_constant);
+    }
+}
+
+// Everything that is shared with other threads has to stay usable from them
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<EventHandler>();
+    assert_send_sync::<HandlerControls>();
+    assert_send_sync::<ScrollCounters>();
+    assert_send_sync::<ScrollDiagnostics>();
+    assert_send_sync::<ScrollHistory>();
+};
+
+// Stores the bits of an f32, which keeps reading lock free
+#[derive(Debug, Default)]
+struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Debug, Default)]
+struct DriftState {
+    // Start of the current run of events that are all below the drift epsilon
+    since: Option<time::SystemTime>,
+    active: bool,
+}
+
+impl EventHandler {
+    pub fn new(config: EventHandlerConfig, enabled: bool, emitter: Emitter) -> Self {
+        let dropped_deltas = Arc::new(Mutex::new(DroppedDeltas::default()));
+        let filter = match config.axis_smoothing {
+            Some((axis_x, axis_y)) => create_axis_filter(
+                axis_x,
+                axis_y,
+                config.time_constant,
+                config.reversal_cushion,
+                config.sign_neutral_zone.0,
+                config.clamp_zero_crossing,
+            ),
+            None => create_filter(
+                config.smoothing,
+                config.time_constant,
+                config.reversal_cushion,
+                config.reversal_policy,
+                config.sign_neutral_zone.0,
+                config.clamp_zero_crossing,
+            ),
+        };
+        let filter = Arc::new(Mutex::new(filter));
+        let power = (config.battery_profile.is_some() || config.ac_profile.is_some())
+            .then(PowerWatcher::default);
+        let power_profile = power
+            .as_ref()
+            .and_then(PowerWatcher::current)
+            .and_then(|source| profile_for_power(&config, source));
+        EventHandler {
+            last_scroll: Arc::new(Mutex::new(None)),
+            filter: filter.clone(),
+            dropped_deltas: dropped_deltas.clone(),
+            drift: Arc::new(Mutex::new(Default::default())),
+            notch_remainder: Arc::new(Mutex::new(0.0)),
+            integer_remainder: Arc::new(Mutex::new((0.0, 0.0))),
+            zoom_remainder: Arc::new(Mutex::new((0.0, 0.0))),
+            key_remainder: Mutex::new(0.0),
+            held_keys: Default::default(),
+            reversal_toggle: if config.reversal_toggle_count > 0 {
+                Some(ReversalToggle::new(
+                    config.reversal_toggle_count,
+                    config.reversal_toggle_window,
+                    config.reversal_toggle_min_distance.0,
+                ))
+            } else {
+                None
+            },
+            timer_output: if config.timer_output_interval.is_zero() {
+                None
+            } else {
+                Some(TimerOutput::new(
+                    config.timer_output_interval,
+                    config.timer_output_catch_up,
+                    emitter.clone(),
+                ))
+            },
+            fling: if config.fling_threshold.0 > 0.0 {
+                Some(Fling::new(
+                    config.fling_curve,
+                    config.fling_duration,
+                    emitter.clone(),
+                ))
+            } else {
+                None
+            },
+            burst_merger: if config.burst_merge_window.is_zero() {
+                None
+            } else {
+                Some(BurstMerger::new(config.burst_merge_window, emitter.clone()))
+            },
+            dropped_flusher: if config.flush_dropped_after.is_zero() {
+                None
+            } else {
+                Some(DroppedFlusher::new(
+                    config.flush_dropped_after,
+                    config.flush_min_distance,
+                    config.flush_min_events,
+                    dropped_deltas,
+                    filter,
+                    emitter.clone(),
+                ))
+            },
+            scroll_end: if config.emit_scroll_end {
+                Some(ScrollEndNotifier::new(config.idle_reset, emitter.clone()))
+            } else {
+                None
+            },
+            rate_limiter: if config.max_lines_per_second == (0.0, 0.0) {
+                None
+            } else {
+                Some(RateLimiter::new(
+                    config.max_lines_per_second.0,
+                    config.max_lines_per_second.1,
+                    emitter.clone(),
+                ))
+            },
+            velocity_lock: VelocityLock::new(config.velocity_lock_max_duration, emitter.clone()),
+            emitter,
+            state_store: None,
+            foreground: Default::default(),
+            display: Default::default(),
+            power,
+            active_profile: Arc::new(Mutex::new(None)),
+            speed_multiplier: Mutex::new(1.0),
+            profile_override: Arc::new(Mutex::new(power_profile)),
+            profile_listener: None,
+            log_boost: None,
+            line_listener: None,
+            scrolled_lines: Mutex::new((0.0, 0)),
+            last_velocity: Mutex::new((0.0, 0.0)),
+            last_output: Mutex::new((0.0, 0.0)),
+            gesture_axis: Mutex::new(None),
+            last_wheel: Mutex::new(None),
+            last_click: Mutex::new(None),
+            gear: Mutex::new(None),
+            gesture_distance: Mutex::new(0.0),
+            gestures: GestureRecorder::new(config.gesture_path.clone(), config.gesture_format),
+            enabled: Arc::new(AtomicBool::new(enabled)),
+            wheel_event_seen: Arc::new(AtomicBool::new(false)),
+            warmup_remaining: AtomicU32::new(config.warmup_events),
+            counters: Arc::new(Default::default()),
+            diagnostics: Arc::new(Default::default()),
+            history: Arc::new(ScrollHistory::new(config.history_size)),
+            toggle_key_down: AtomicBool::new(false),
+            last_gap: Mutex::new(None),
+            tuning: Arc::new(Mutex::new(config.tuning())),
+            config,
+            start_time: time::SystemTime::now(),
+        }
+    }
+
+    // Everything else goes by the event timestamps, only the soft start counts from the launch time
+    pub fn with_clock(mut self, clock: &dyn Clock) -> Self {
+        self.start_time = clock.now();
+        self
+    }
+
+    pub fn with_profile_listener(
+        mut self,
+        listener: impl Fn(Option<&Profile>) + Send + Sync + 'static,
+    ) -> Self {
+        self.profile_listener = Some(Box::new(listener));
+        self
+    }
+
+    pub fn with_state_store(mut self, state_store: StateStore) -> Self {
+        self.state_store = Some(state_store);
+        self
+    }
+
+    pub fn with_log_boost(mut self, log_boost: impl Fn() + Send + Sync + 'static) -> Self {
+        self.log_boost = Some(Box::new(log_boost));
+        self
+    }
+
+    // Only called when announce_every_lines is set
+    pub fn with_line_listener(mut self, listener: impl Fn(i64) + Send + Sync + 'static) -> Self {
+        self.line_listener = Some(Box::new(listener));
+        self
+    }
+
+    pub fn enabled(&self) -> Arc<AtomicBool> {
+        self.enabled.clone()
+    }
+
+    // Name of the profile that was used for the last scroll event
+    pub fn active_profile_name(&self) -> Arc<Mutex<Option<String>>> {
+        self.active_profile.clone()
+    }
+
+    pub fn controls(&self) -> HandlerControls {
+        HandlerControls {
+            enabled: self.enabled.clone(),
+            state_store: self.state_store.clone(),
+            active_profile: self.active_profile.clone(),
+            profile_override: self.profile_override.clone(),
+            tuning: self.tuning.clone(),
+            filter: self.filter.clone(),
+        }
+    }
+
+    pub fn counters(&self) -> Arc<ScrollCounters> {
+        self.counters.clone()
+    }
+
+    pub fn diagnostics(&self) -> Arc<ScrollDiagnostics> {
+        self.diagnostics.clone()
+    }
+
+    pub fn history(&self) -> Arc<ScrollHistory> {
+        self.history.clone()
+    }
+
+    // Set once the first wheel event arrives, used by the startup watchdog
+    pub fn wheel_event_seen(&self) -> Arc<AtomicBool> {
+        self.wheel_event_seen.clone()
+    }
+
+    pub fn callback(&self, event: Event) -> Option<Event> {
+        if let EventType::Wheel(_) = event.event_type {
+            self.wheel_event_seen.store(true, Ordering::Relaxed);
+            if self.emitter.is_own_event(&event.event_type) {
+                return Some(event);
+            }
+        }
+        if let EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)) = event.event_type {
+            self.gestures.record(event.time, delta_x, delta_y);
+            // Scrolling by hand takes over again
+            self.velocity_lock.release();
+            // Works while smoothing is off as well, otherwise it couldn't be turned back on
+            if let Some(reversal_toggle) = &self.reversal_toggle {
+                if reversal_toggle.observe(event.time, delta_y) {
+                    info!("Scroll pattern recognized");
+                    let enabled = toggle_enabled(&self.enabled, self.state_store.as_ref());
+                    if self.config.notify_on_toggle {
+                        notify(
+                            "Smoother scroll".to_string(),
+                            format!("Smoothing {}", if enabled { "on" } else { "off" }),
+                        );
+                    }
+                }
+            }
+            if let Some(trace) = self.emitter.trace() {
+                trace.raw(delta_x, delta_y);
+            }
+        }
+
+        let output = self.route(event);
+        if let Some(trace) = self.emitter.trace() {
+            if let Some((delta_x, delta_y)) = output.as_ref().and_then(wheel_delta) {
+                trace.output(delta_x, delta_y);
+            }
+        }
+        output
+    }
+
+    fn route(&self, event: Event) -> Option<Event> {
+        let is_key_repeat =
+            matches!(event.event_type, EventType::KeyPress(key) if self.held_keys.is_held(key));
+        self.held_keys.update(&event.event_type);
+        if let EventType::ButtonPress(_) = event.event_type {
+            if !self.config.click_scroll_cooldown.is_zero() {
+                *self.last_click.lock().unwrap() = Some(event.time);
+            }
+        }
+
+        match event.event_type {
+            EventType::KeyPress(key) if Some(key) == self.config.toggle_key => {
+                if !self.toggle_key_down.swap(true, Ordering::Relaxed) {
+                    toggle_enabled(&self.enabled, self.state_store.as_ref());
+                }
+                None
+            }
+            EventType::KeyRelease(key) if Some(key) == self.config.toggle_key => {
+                self.toggle_key_down.store(false, Ordering::Relaxed);
+                None
+            }
+            EventType::KeyPress(key) if Some(key) == self.config.record_key => {
+                if !is_key_repeat {
+                    self.gestures.toggle_recording();
+                }
+                None
+            }
+            EventType::KeyPress(key) if Some(key) == self.config.replay_key => {
+                if !is_key_repeat {
+                    self.gestures.replay();
+                }
+                None
+            }
+            EventType::KeyPress(key) if Some(key) == self.config.velocity_lock_key => {
+                if !is_key_repeat {
+                    let (velocity_x, velocity_y) = *self.last_velocity.lock().unwrap();
+                    self.velocity_lock.toggle(velocity_x, velocity_y);
+                }
+                None
+            }
+            EventType::KeyPress(key) if Some(key) == self.config.log_boost_key => {
+                if !is_key_repeat {
+                    if let Some(log_boost) = &self.log_boost {
+                        log_boost();
+                    }
+                }
+                None
+            }
+            EventType::KeyRelease(key)
+                if Some(key) == self.config.record_key
+                    || Some(key) == self.config.replay_key
+                    || Some(key) == self.config.velocity_lock_key
+                    || Some(key) == self.config.log_boost_key =>
+            {
+                None
+            }
+            _ if !self.enabled.load(Ordering::Relaxed) => Some(event),
+            EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)) => {
+                self.handle_wheel(event, delta_x, delta_y)
+            }
+            _ => Some(event),
+        }
+    }
+
+    fn handle_wheel(&self, event: Event, delta_x: f32, delta_y: f32) -> Option<Event> {
+        if let Some(scroll_end) = &self.scroll_end {
+            scroll_end.schedule();
+        }
+        // Momentum from the previous window shouldn't carry over into the next one
+        if self.config.reset_on_focus_change && self.foreground.focus_changed() {
+            info!("Focus changed, resetting the smoothing");
+            self.reset_state();
+            if let Some(fling) = &self.fling {
+                fling.stop();
+            }
+        }
+        if self.config.reset_on_display_change {
+            if let Some(layout) = self.display.changed() {
+                info!(
+                    "Display layout changed to {:?}, resetting the smoothing",
+                    layout
+                );
+                self.reset_state();
+                self.follow_refresh_rate(layout.refresh_rate);
+            }
+        }
+        if let Some(source) = self.power.as_ref().and_then(PowerWatcher::changed) {
+            let profile = profile_for_power(&self.config, source);
+            info!(
+                "Running on {:?} now, using the profile {:?}",
+                source, profile
+            );
+            *self.profile_override.lock().unwrap() = profile;
+        }
+
+        // Inverting before smoothing keeps the smoothing state in line with what is sent
+        let profile = self.active_profile();
+        let invert_x = profile
+            .and_then(|profile| profile.invert_x)
+            .unwrap_or(self.config.invert_x);
+        let invert_y = profile
+            .and_then(|profile| profile.invert_y)
+            .unwrap_or(self.config.invert_y);
+        let delta_x = if invert_x { -delta_x } else { delta_x };
+        let delta_y = if invert_y { -delta_y } else { delta_y };
+        let speed_multiplier = profile
+            .and_then(|profile| profile.speed_multiplier)
+            .unwrap_or(1.0);
+        *self.speed_multiplier.lock().unwrap() = speed_multiplier;
+        let delta_x = delta_x * speed_multiplier;
+        let delta_y = delta_y * speed_multiplier;
+        let event = set_wheel_delta(event, delta_x, delta_y);
+
+        if self.is_warming_up() {
+            return match self.config.warmup_action {
+                WarmupAction::Pass => Some(event),
+                WarmupAction::Drop => None,
+            };
+        }
+        if self.is_click_cooldown(event.time) {
+            debug!("Dropping a scroll event right after a click");
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        if let Some(zoom_modifier) = self.config.zoom_modifier {
+            if self.held_keys.is_modifier_held(zoom_modifier) {
+                return self.handle_zoom(event);
+            }
+        }
+        if self.config.bypass_while_dragging && self.held_keys.is_primary_button_held() {
+            return Some(event);
+        }
+        if self.is_bypassed_window() {
+            return Some(event);
+        }
+        if self.is_duplicate(event.time, delta_x, delta_y) {
+            debug!("Dropping a duplicate of the previous scroll event");
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        // A flick seeds a fling instead of jumping the whole way at once
+        let fling = self
+            .fling
+            .as_ref()
+            .filter(|_| profile.and_then(|profile| profile.fling).unwrap_or(true));
+        if let Some(fling) = fling {
+            if Lines(delta_x.abs().max(delta_y.abs())) >= self.config.fling_threshold {
+                fling.start(delta_x, delta_y);
+                return None;
+            }
+            fling.interrupt(delta_x, delta_y);
+        }
+
+        let output = self.filter_wheel(event, delta_x, delta_y);
+        let output = match &self.rate_limiter {
+            Some(rate_limiter) => output.and_then(|event| {
+                let (delta_x, delta_y) = wheel_delta(&event)?;
+                let (limited_x, limited_y) = rate_limiter.limit(event.time, delta_x, delta_y);
+                with_wheel_delta(event, limited_x, limited_y)
+            }),
+            None => output,
+        };
+        let output = if self.config.max_gesture_distance.0 > 0.0 {
+            output.and_then(|event| self.cap_gesture_distance(event))
+        } else {
+            output
+        };
+        let output = if self.config.emit_notches {
+            output.and_then(|event| self.split_into_notches(event))
+        } else {
+            output
+        };
+        let force_integer_notches = profile
+            .and_then(|profile| profile.force_integer_notches)
+            .unwrap_or(self.config.force_integer_notches);
+        let output = if force_integer_notches {
+            output.and_then(|event| self.round_to_integer_notches(event))
+        } else {
+            output
+        };
+
+        if let Some(scroll_keys) = profile.and_then(|profile| profile.scroll_keys) {
+            let lines_per_key = profile
+                .and_then(|profile| profile.lines_per_key)
+                .unwrap_or_else(|| scroll_keys.default_lines_per_key());
+            return output
+                .and_then(|event| self.send_as_key_presses(event, scroll_keys, lines_per_key));
+        }
+
+        let output = if self.config.output_jitter_epsilon.0 > 0.0 {
+            output.and_then(|event| self.suppress_jitter(event))
+        } else {
+            output
+        };
+
+        if let Some((_, delta_y)) = output.as_ref().and_then(wheel_delta) {
+            self.count_lines(delta_y);
+        }
+        if let Some(burst_merger) = &self.burst_merger {
+            if let Some((delta_x, delta_y)) = output.as_ref().and_then(wheel_delta) {
+                burst_merger.add(delta_x, delta_y);
+                return None;
+            }
+        }
+        // Nothing that went through the smoothing is passed on directly, the timer sends it all
+        let timer_output = self.timer_output.as_ref().filter(|_| {
+            profile
+                .and_then(|profile| profile.timer_output)
+                .unwrap_or(true)
+        });
+        if let Some(timer_output) = timer_output {
+            if let Some((delta_x, delta_y)) = output.as_ref().and_then(wheel_delta) {
+                timer_output.add(delta_x, delta_y);
+            }
+            return None;
+        }
+        output
+    }
+
+    // Tells the line listener whenever the gesture crosses another multiple of announce_every_lines
+    fn count_lines(&self, delta_y: f32) {
+        let Some(listener) = &self.line_listener else {
+            return;
+        };
+        let every = self.config.announce_every_lines;
+        if every <= 0.0 {
+            return;
+        }
+        let mut scrolled_lines = self.scrolled_lines.lock().unwrap();
+        scrolled_lines.0 += delta_y;
+        let stretches = (scrolled_lines.0 / every).trunc() as i64;
+        if stretches == scrolled_lines.1 {
+            return;
+        }
+        scrolled_lines.1 = stretches;
+        let lines = scrolled_lines.0.trunc() as i64;
+        drop(scrolled_lines);
+        listener(lines);
+    }
+
+    // Wheels are easily nudged while clicking. Once the cooldown after a click is over, the
+    // smoothing starts over, so that nothing from before the click carries into the next scroll.
+    fn is_click_cooldown(&self, timestamp: time::SystemTime) -> bool {
+        let mut last_click = self.last_click.lock().unwrap();
+        let Some(click) = *last_click else {
+            return false;
+        };
+        if timestamp.duration_since(click).unwrap_or_default() < self.config.click_scroll_cooldown {
+            return true;
+        }
+        *last_click = None;
+        drop(last_click);
+        self.reset_state();
+        false
+    }
+
+    // The same deltas again, within the duplicate window of the last event that was let through
+    fn is_duplicate(&self, timestamp: time::SystemTime, delta_x: f32, delta_y: f32) -> bool {
+        if self.config.duplicate_window.is_zero() {
+            return false;
+        }
+        let mut last_wheel = self.last_wheel.lock().unwrap();
+        if let Some(last) = &*last_wheel {
+            let elapsed = timestamp.duration_since(last.timestamp);
+            if (last.delta_x, last.delta_y) == (delta_x, delta_y)
+                && matches!(elapsed, Ok(elapsed) if elapsed <= self.config.duplicate_window)
+            {
+                return true;
+            }
+        }
+        *last_wheel = Some(ScrollWithTimestamp {
+            delta_x,
+            delta_y,
+            timestamp,
+        });
+        false
+    }
+
+    fn is_bypassed_window(&self) -> bool {
+        if self.config.bypass_window_classes.is_empty() {
+            return false;
+        }
+        let Some(class_name) = self.foreground.class_name() else {
+            return false;
+        };
+        self.config
+            .bypass_window_classes
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&class_name))
+    }
+
+    // The tuned thresholds, in the units of the deltas after the speed_multiplier
+    fn thresholds(&self) -> Tuning {
+        let tuning = *self.tuning.lock().unwrap();
+        if self.config.scale_thresholds_by_multiplier {
+            tuning.scaled(*self.speed_multiplier.lock().unwrap())
+        } else {
+            tuning
+        }
+    }
+
+    fn active_profile(&self) -> Option<&Profile> {
+        if self.config.profiles.is_empty() {
+            return None;
+        }
+        let profile_override = self.profile_override.lock().unwrap().clone();
+        let profile = match profile_override {
+            Some(name) => self
+                .config
+                .profiles
+                .iter()
+                .find(|profile| profile.name == name),
+            None => {
+                let executable = self.foreground.executable();
+                find_profile(&self.config.profiles, executable.as_deref())
+            }
+        };
+
+        let mut active_profile = self.active_profile.lock().unwrap();
+        let name = profile.map(|profile| &profile.name);
+        if active_profile.as_ref() != name {
+            info!("Active profile: {:?}", name);
+            *active_profile = name.cloned();
+            if let Some(listener) = &self.profile_listener {
+                listener(profile);
+            }
+        }
+        profile
+    }
+
+    fn filter_wheel(&self, event: Event, delta_x: f32, delta_y: f32) -> Option<Event> {
+        let timestamp = event.time;
+        self.counters.processed.fetch_add(1, Ordering::Relaxed);
+        self.reset_if_idle(timestamp);
+        let (delta_x, delta_y) = if self.config.lock_dominant_axis_per_gesture {
+            self.lock_to_gesture_axis(delta_x, delta_y)
+        } else {
+            (delta_x, delta_y)
+        };
+        let event = set_wheel_delta(event, delta_x, delta_y);
+
+        // Axes that aren't smoothed are passed through as they are, and never touch the smoothing state
+        let (filter_x, filter_y) = match self.config.smooth_axes {
+            SmoothAxes::Both => (delta_x, delta_y),
+            SmoothAxes::Vertical => (0.0, delta_y),
+            SmoothAxes::Horizontal => (delta_x, 0.0),
+        };
+        let raw_x = delta_x - filter_x;
+        let raw_y = delta_y - filter_y;
+        if filter_x == 0.0 && filter_y == 0.0 && (raw_x != 0.0 || raw_y != 0.0) {
+            return Some(event);
+        }
+
+        if self.is_drifting(timestamp, filter_x, filter_y) {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            *self.dropped_deltas.lock().unwrap() = Default::default();
+            return with_wheel_delta(event, raw_x, raw_y);
+        }
+        let is_gesture_start = self.is_gesture_start();
+        // Always updates the state, so the first tick of a gesture still seeds the smoothing
+        let (should_keep_event, output_x, output_y) =
+            self.handle_mouse_scroll(timestamp, filter_x, filter_y);
+        if self.config.passthrough_first_tick && is_gesture_start {
+            *self.dropped_deltas.lock().unwrap() = Default::default();
+            // The whole tick was sent, so nothing of it is left to send later
+            self.filter.lock().unwrap().take_backlog();
+            Some(event)
+        } else if should_keep_event {
+            *self.dropped_deltas.lock().unwrap() = Default::default();
+            // The smoothing can leave a fading remainder on an axis that isn't being scrolled
+            let (output_x, output_y) = if self.config.suppress_cross_axis {
+                (
+                    if delta_x == 0.0 { 0.0 } else { output_x },
+                    if delta_y == 0.0 { 0.0 } else { output_y },
+                )
+            } else {
+                (output_x, output_y)
+            };
+            with_wheel_delta(event, output_x + raw_x, output_y + raw_y)
+        } else {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            let held_back = self.filter.lock().unwrap().hold_back(output_x, output_y);
+            let mut dropped_deltas = self.dropped_deltas.lock().unwrap();
+            if held_back {
+                dropped_deltas.count(filter_x, filter_y);
+            } else {
+                // Capped, so that scrolling against the end of a page doesn't build up a backlog
+                dropped_deltas.add(filter_x, filter_y, self.config.max_dropped_deltas);
+            }
+            drop(dropped_deltas);
+            if let Some(dropped_flusher) = &self.dropped_flusher {
+                dropped_flusher.schedule();
+            }
+            with_wheel_delta(event, raw_x, raw_y)
+        }
+    }
+
+    // A free-spinning wheel can keep one gesture going for a very long way. Once the gesture has
+    // sent max_gesture_distance, the rest of it is dropped until the next idle reset.
+    fn cap_gesture_distance(&self, event: Event) -> Option<Event> {
+        let Some((delta_x, delta_y)) = wheel_delta(&event) else {
+            return Some(event);
+        };
+        let distance = delta_x.abs().max(delta_y.abs());
+        let cap = self.config.max_gesture_distance.0;
+        let mut gesture_distance = self.gesture_distance.lock().unwrap();
+        let left = cap - *gesture_distance;
+        if left <= 0.0 {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        if distance < left {
+            *gesture_distance += distance;
+            return Some(event);
+        }
+        // Only as much as still fits is sent
+        *gesture_distance = cap;
+        info!("Gesture reached max_gesture_distance, dropping the rest of it");
+        let scale = left / distance;
+        with_wheel_delta(event, delta_x * scale, delta_y * scale)
+    }
+
+    // Some apps only react to whole notches. The vertical distance is collected until it adds
+    // up to full lines, which are then sent as separate single line events.
+    fn split_into_notches(&self, event: Event) -> Option<Event> {
+        let Some((delta_x, delta_y)) = wheel_delta(&event) else {
+            return Some(event);
+        };
+
+        // Notches beyond the cap stay in the remainder, and are sent with the next events
+        let (notch, count) = {
+            let mut notch_remainder = self.notch_remainder.lock().unwrap();
+            *notch_remainder += delta_y;
+            let notches = notch_remainder.trunc();
+            let count = (notches.abs() as u32).min(self.config.max_notches_per_event);
+            let notch = notches.signum();
+            *notch_remainder -= notch * count as f32;
+            (notch, count)
+        };
+        if count == 0 {
+            return with_wheel_delta(event, delta_x, 0.0);
+        }
+
+        if self.config.coalesce_output {
+            return with_wheel_delta(event, delta_x, notch * count as f32);
+        }
+        for _ in 1..count {
+            self.emitter
+                .emit(EventType::Wheel(MouseScrollDelta::LineDelta(0.0, notch)));
+        }
+        with_wheel_delta(event, delta_x, notch)
+    }
+
+    // Steady scrolling can produce outputs that wobble slightly around the same value, which
+    // some apps render as micro-jitter. Outputs that barely differ from the last one repeat it.
+    fn suppress_jitter(&self, event: Event) -> Option<Event> {
+        let Some((delta_x, delta_y)) = wheel_delta(&event) else {
+            return Some(event);
+        };
+        let epsilon = self.config.output_jitter_epsilon.0;
+        let mut last_output = self.last_output.lock().unwrap();
+        let steady = |delta: f32, last: f32| {
+            if delta * last > 0.0 && (delta - last).abs() < epsilon {
+                last
+            } else {
+                delta
+            }
+        };
+        *last_output = (
+            steady(delta_x, last_output.0),
+            steady(delta_y, last_output.1),
+        );
+        with_wheel_delta(event, last_output.0, last_output.1)
+    }
+
+    // The vertical distance is collected until it adds up to a key press. Horizontal scrolling
+    // is still sent as it is.
+    fn send_as_key_presses(
+        &self,
+        event: Event,
+        scroll_keys: ScrollKeys,
+        lines_per_key: f32,
+    ) -> Option<Event> {
+        let Some((delta_x, delta_y)) = wheel_delta(&event) else {
+            return Some(event);
+        };
+
+        let presses = {
+            let mut key_remainder = self.key_remainder.lock().unwrap();
+            *key_remainder += delta_y / lines_per_key;
+            let presses = key_remainder.trunc();
+            *key_remainder -= presses;
+            presses
+        };
+        // Positive deltas scroll up
+        let key = match (scroll_keys, presses > 0.0) {
+            (ScrollKeys::Arrows, true) => Key::UpArrow,
+            (ScrollKeys::Arrows, false) => Key::DownArrow,
+            (ScrollKeys::Pages, true) => Key::PageUp,
+            (ScrollKeys::Pages, false) => Key::PageDown,
+        };
+        for _ in 0..presses.abs() as u32 {
+            self.emitter.emit(EventType::KeyPress(key));
+            self.emitter.emit(EventType::KeyRelease(key));
+        }
+
+        if delta_x == 0.0 {
+            None
+        } else {
+            with_wheel_delta(event, delta_x, 0.0)
+        }
+    }
+
+    // Legacy apps that ignore fractional lines still scroll, just in coarser steps
+    fn round_to_integer_notches(&self, event: Event) -> Option<Event> {
+        quantize(
+            event,
+            1.0,
+            self.config.rounding_mode,
+            &self.integer_remainder,
+        )
+    }
+
+    // Zooming skips the smoothing, so that every zoom step happens right away
+    fn handle_zoom(&self, event: Event) -> Option<Event> {
+        if self.config.zoom_step.0 > 0.0 {
+            quantize(
+                event,
+                self.config.zoom_step.0,
+                self.config.rounding_mode,
+                &self.zoom_remainder,
+            )
+        } else {
+            Some(event)
+        }
+    }
+
+    // The first event of a gesture that is larger than drift decides its axis.
+    // The other axis stays still until the next idle reset.
+    fn lock_to_gesture_axis(&self, delta_x: f32, delta_y: f32) -> (f32, f32) {
+        let mut gesture_axis = self.gesture_axis.lock().unwrap();
+        if gesture_axis.is_none()
+            && Lines(delta_x.abs().max(delta_y.abs())) > self.config.drift_epsilon
+        {
+            *gesture_axis = Some(if delta_x.abs() > delta_y.abs() {
+                Axis::Horizontal
+            } else {
+                Axis::Vertical
+            });
+        }
+        match *gesture_axis {
+            Some(Axis::Horizontal) => (delta_x, 0.0),
+            Some(Axis::Vertical) => (0.0, delta_y),
+            None => (delta_x, delta_y),
+        }
+    }
+
+    // Counts down the warm-up, and returns true while it is still running
+    fn is_warming_up(&self) -> bool {
+        self.warmup_remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    // Scales the time constant linearly from zero at launch to the configured one at the end of the soft start
+    fn soft_start_time_constant(&self, timestamp: time::SystemTime) -> time::Duration {
+        let elapsed = timestamp
+            .duration_since(self.start_time)
+            .unwrap_or_default();
+        let progress = (elapsed.as_secs_f32() / self.config.soft_start.as_secs_f32()).min(1.0);
+        self.tuning.lock().unwrap().time_constant.mul_f32(progress)
+    }
+
+    // Nothing has been scrolled since launch or since the last idle reset
+    fn is_gesture_start(&self) -> bool {
+        self.last_scroll.lock().unwrap().is_none()
+    }
+
+    // The stored timestamps are stale after a long pause, so the next scroll is treated like the very first one
+    fn reset_if_idle(&self, timestamp: time::SystemTime) {
+        let Some(last_timestamp) = self
+            .last_scroll
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|last_scroll| last_scroll.timestamp)
+        else {
+            return;
+        };
+        let idle_duration = timestamp.duration_since(last_timestamp).unwrap_or_default();
+        if idle_duration > self.config.idle_reset {
+            info!("Resetting after {:?} without scrolling", idle_duration);
+            // A conserving filter can still hold part of the last gesture, which would be lost
+            let (backlog_x, backlog_y) = self.filter.lock().unwrap().take_backlog();
+            if backlog_x != 0.0 || backlog_y != 0.0 {
+                self.emitter
+                    .emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+                        backlog_x, backlog_y,
+                    )));
+            }
+            self.reset_state();
+        }
+    }
+
+    // A new display can run at another rate, and merged events and timer ticks should still
+    // come once per frame
+    fn follow_refresh_rate(&self, refresh_rate: Option<u32>) {
+        let Some(refresh_rate) = refresh_rate.filter(|_| self.config.match_refresh_rate) else {
+            return;
+        };
+        let frame_time = time::Duration::from_secs_f32(1.0 / refresh_rate as f32);
+        if let Some(burst_merger) = &self.burst_merger {
+            info!(
+                "Display refresh rate: {} Hz, sending merged scroll events every {:?}",
+                refresh_rate, frame_time
+            );
+            burst_merger.set_window(frame_time);
+        }
+        if let Some(timer_output) = &self.timer_output {
+            info!(
+                "Display refresh rate: {} Hz, sending timed scroll events every {:?}",
+                refresh_rate, frame_time
+            );
+            timer_output.set_interval(frame_time);
+        }
+    }
+
+    // Starts over as if nothing had been scrolled yet
+    fn reset_state(&self) {
+        *self.last_scroll.lock().unwrap() = None;
+        self.filter.lock().unwrap().reset();
+        *self.dropped_deltas.lock().unwrap() = Default::default();
+        *self.notch_remainder.lock().unwrap() = 0.0;
+        *self.integer_remainder.lock().unwrap() = (0.0, 0.0);
+        *self.last_velocity.lock().unwrap() = (0.0, 0.0);
+        *self.gesture_axis.lock().unwrap() = None;
+        *self.gesture_distance.lock().unwrap() = 0.0;
+        *self.scrolled_lines.lock().unwrap() = (0.0, 0);
+        *self.gear.lock().unwrap() = None;
+        *self.last_gap.lock().unwrap() = None;
+        *self.last_output.lock().unwrap() = (0.0, 0.0);
+        *self.key_remainder.lock().unwrap() = 0.0;
+        // The rate limiter isn't reset, it still sends what it holds back at the allowed rate
+    }
+
+    // Free-spinning wheels can keep reporting tiny deltas while idle.
+    // Once that has gone on for long enough, everything is dropped until a real scroll starts.
+    fn is_drifting(&self, timestamp: time::SystemTime, delta_x: f32, delta_y: f32) -> bool {
+        let mut drift = self.drift.lock().unwrap();
+        let magnitude = Lines(delta_x.abs().max(delta_y.abs()));
+
+        if magnitude < self.config.drift_epsilon {
+            let since = *drift.since.get_or_insert(timestamp);
+            let duration = timestamp.duration_since(since).unwrap_or_default();
+            if !drift.active && duration >= self.config.drift_window {
+                info!("Drift detected, dropping scroll events");
+                drift.active = true;
+                if let Some(last_scroll) = self.last_scroll.lock().unwrap().as_mut() {
+                    last_scroll.delta_x = 0.0;
+                    last_scroll.delta_y = 0.0;
+                }
+                self.filter.lock().unwrap().reset();
+            }
+        } else {
+            drift.since = None;
+        }
+
+        if drift.active && magnitude >= self.thresholds().always_pass_above {
+            drift.active = false;
+        }
+        drift.active
+    }
+
+    // Two quick ticks in the same direction after a pause, like a double tap of the wheel.
+    // Single ticks and continuous scrolling never count, since the tick before has to be isolated.
+    // The first tick after a launch or reset has no gap to tell, so it never starts a boost.
+    fn is_boost(
+        &self,
+        previous: Option<&ScrollWithTimestamp>,
+        gap: time::Duration,
+        delta_x: f32,
+        delta_y: f32,
+    ) -> bool {
+        // The gap of a tick without a previous one is made up by the cold start
+        let measured_gap = previous.map(|_| gap);
+        let previous_gap = std::mem::replace(&mut *self.last_gap.lock().unwrap(), measured_gap);
+        let window = self.config.boost_window;
+        let Some(previous) = previous.filter(|_| !window.is_zero()) else {
+            return false;
+        };
+
+        let is_tick = |delta_x: f32, delta_y: f32| {
+            Lines(delta_x.abs().max(delta_y.abs())) >= self.config.boost_min_distance
+        };
+        let same_direction = delta_x * previous.delta_x > 0.0 || delta_y * previous.delta_y > 0.0;
+        gap <= window
+            && matches!(previous_gap, Some(previous_gap) if previous_gap > window)
+            && same_direction
+            && is_tick(delta_x, delta_y)
+            && is_tick(previous.delta_x, previous.delta_y)
+    }
+
+    // All cases where the smoothing can't decide end up here, so that they behave the same
+    fn on_uncertain(&self, delta_x: f32, delta_y: f32) -> (bool, f32, f32) {
+        (
+            self.config.on_uncertain == UncertainAction::Pass,
+            delta_x,
+            delta_y,
+        )
+    }
+
+    // Feeds an event into the smoothing state, and returns the smoothed deltas and speeds
+    fn update_scroll(
+        &self,
+        timestamp: time::SystemTime,
+        delta_x: f32,
+        delta_y: f32,
+    ) -> ScrollUpdate {
+        // Add new event
+        let last_delta = {
+            let mut last_delta_mutex = self.last_scroll.lock().unwrap();
+            let last_delta = last_delta_mutex.clone();
+
+            // Events with the same timestamp as the previous one, which high polling rates can produce,
+            // deliberately update the state like any other. No time has passed for them, so they count as
+            // fast enough to keep (see `speed`), and the exponential smoothing gives them no weight.
+            // Only events that are older than the previous one leave the state alone.
+            if !matches!(&last_delta, Some(last_delta) if timestamp < last_delta.timestamp) {
+                *last_delta_mutex = Some(ScrollWithTimestamp {
+                    delta_x,
+                    delta_y,
+                    timestamp,
+                });
+            }
+            last_delta
+        };
+
+        // Uses the event time instead of the current time, so that scripted events behave the same as live ones
+        let duration = match &last_delta {
+            Some(last_delta) => match timestamp.duration_since(last_delta.timestamp) {
+                Ok(duration) => duration,
+                Err(_) => {
+                    // An event older than the previous one has no meaningful time in between
+                    return ScrollUpdate::Decided(self.on_uncertain(delta_x, delta_y));
+                }
+            },
+            // There is nothing to measure the time against. An endless gap gives the first delta
+            // all the weight. Ramping in counts it as one time constant after a standstill, which
+            // gives it a share of it. No gap at all would give it no weight, and no speed to keep it by.
+            None => match self.config.cold_start {
+                ColdStart::FirstDelta => time::Duration::MAX,
+                ColdStart::Zero => self.tuning.lock().unwrap().time_constant,
+            },
+        };
+
+        if self.is_boost(last_delta.as_ref(), duration, delta_x, delta_y) {
+            info!("Scroll boost");
+            let multiplier = self.config.boost_multiplier;
+            return ScrollUpdate::Decided((true, delta_x * multiplier, delta_y * multiplier));
+        }
+
+        let (smoothed_x, smoothed_y, alpha) = {
+            let mut filter = match self.filter.lock() {
+                Ok(filter) => filter,
+                Err(poisoned) => {
+                    warn!("The smoothing state was left behind by a panic, resetting it");
+                    poisoned.into_inner().reset();
+                    self.filter.clear_poison();
+                    return ScrollUpdate::Decided(self.on_uncertain(delta_x, delta_y));
+                }
+            };
+            if !self.config.soft_start.is_zero() {
+                filter.set_time_constant(self.soft_start_time_constant(timestamp));
+            }
+            let (smoothed_x, smoothed_y) = filter.apply(delta_x, delta_y, duration);
+            if !smoothed_x.is_finite() || !smoothed_y.is_finite() {
+                warn!(
+                    "The smoothing produced ({}, {}), resetting it",
+                    smoothed_x, smoothed_y
+                );
+                filter.reset();
+                drop(filter);
+                return ScrollUpdate::Decided(self.on_uncertain(delta_x, delta_y));
+            }
+            let (smoothed_x, smoothed_y) = if self.config.clamp_overshoot {
+                let clamped_x = clamp_overshoot(smoothed_x, delta_x);
+                let clamped_y = clamp_overshoot(smoothed_y, delta_y);
+                // A conserving filter sends the cut off part later on
+                filter.hold_back(smoothed_x - clamped_x, smoothed_y - clamped_y);
+                (clamped_x, clamped_y)
+            } else {
+                (smoothed_x, smoothed_y)
+            };
+            (smoothed_x, smoothed_y, filter.last_alpha())
+        };
+
+        // Slow scrolling is sent as it is, so that single lines stay precise.
+        // The filter still sees it, so that speeding up continues smoothly.
+        let raw_speed = speed(delta_x.abs().max(delta_y.abs()), duration);
+        let (smoothed_x, smoothed_y) = if raw_speed < self.config.smoothing_velocity_floor {
+            (delta_x, delta_y)
+        } else {
+            (smoothed_x, smoothed_y)
+        };
+        self.diagnostics.raw.0.store(delta_x);
+        self.diagnostics.raw.1.store(delta_y);
+        self.diagnostics.smoothed.0.store(smoothed_x);
+        self.diagnostics.smoothed.1.store(smoothed_y);
+        self.diagnostics.alpha.store(alpha);
+
+        let speed_x = speed(smoothed_x, duration);
+        let speed_y = speed(smoothed_y, duration);
+
+        // Events without time in between have no meaningful speed
+        if speed_x.is_finite() && speed_y.is_finite() {
+            *self.last_velocity.lock().unwrap() = (speed_x, speed_y);
+        }
+
+        ScrollUpdate::Smoothed {
+            smoothed: (smoothed_x, smoothed_y),
+            speed: (speed_x, speed_y),
+            alpha,
+        }
+    }
+
+    // Multiplier of the gear for the speed, after shifting if needed
+    fn shift_gear(&self, speed: f32) -> f32 {
+        let mut gear = self.gear.lock().unwrap();
+        let next = select_gear(
+            &self.config.gears,
+            *gear,
+            speed,
+            self.config.gear_hysteresis,
+        );
+        if next != *gear {
+            // Gear 0 is below the first one
+            debug!(
+                "Shifted from gear {} to {} at {} lines per millisecond",
+                gear.map_or(0, |index| index + 1),
+                next.map_or(0, |index| index + 1),
+                speed
+            );
+            *gear = next;
+        }
+        next.map_or(1.0, |index| self.config.gears[index].1)
+    }
+
+    // Returns whether to keep the event, and the smoothed deltas
+    fn handle_mouse_scroll(
+        &self,
+        timestamp: time::SystemTime,
+        delta_x: f32,
+        delta_y: f32,
+    ) -> (bool, f32, f32) {
+        let ((smoothed_x, smoothed_y), (speed_x, speed_y), alpha) =
+            match self.update_scroll(timestamp, delta_x, delta_y) {
+                ScrollUpdate::Decided(result) => return result,
+                ScrollUpdate::Smoothed {
+                    smoothed,
+                    speed,
+                    alpha,
+                } => (smoothed, speed, alpha),
+            };
+        let tuning = self.thresholds();
+        let decision = should_keep(
+            (speed_x, speed_y),
+            (delta_x, delta_y),
+            &tuning,
+            &self.config,
+        );
+        self.history.record(HistoryEntry {
+            raw: (delta_x, delta_y),
+            smoothed: (smoothed_x, smoothed_y),
+            alpha,
+            decision,
+            timestamp,
+        });
+
+        // Scales the output by speed, after the keep or drop decision.
+        // Events without time in between are scaled like the one before them.
+        let speed = match speed_x.abs().max(speed_y.abs()) {
+            speed if speed.is_finite() => speed,
+            _ => {
+                let (last_x, last_y) = *self.last_velocity.lock().unwrap();
+                last_x.abs().max(last_y.abs())
+            }
+        };
+        let gain = if self.config.gears.is_empty() {
+            gain(&self.config.gain_curve, speed)
+        } else {
+            self.shift_gear(speed)
+        };
+
+        (
+            decision != ScrollDecision::Drop,
+            smoothed_x * gain,
+            smoothed_y * gain,
+        )
+    }
+}
+
+// Without a profile for the power source, the focused app picks the profile as usual
+fn profile_for_power(config: &EventHandlerConfig, source: PowerSource) -> Option<String> {
+    match source {
+        PowerSource::Battery => config.battery_profile.clone(),
+        PowerSource::Ac => config.ac_profile.clone(),
+    }
+}
+
+// Rounds to whole multiples of `step`, and carries whatever got rounded away over to the next event
+fn quantize(
+    event: Event,
+    step: f32,
+    rounding_mode: RoundingMode,
+    remainder: &Mutex<(f32, f32)>,
+) -> Option<Event> {
+    let Some((delta_x, delta_y)) = wheel_delta(&event) else {
+        return Some(event);
+    };
+
+    let mut remainder = remainder.lock().unwrap();
+    let total_x = remainder.0 + delta_x;
+    let total_y = remainder.1 + delta_y;
+    let quantized_x = rounding_mode.round(total_x / step) * step;
+    let quantized_y = rounding_mode.round(total_y / step) * step;
+    *remainder = (total_x - quantized_x, total_y - quantized_y);
+    with_wheel_delta(event, quantized_x, quantized_y)
+}
+
+fn wheel_delta(event: &Event) -> Option<(f32, f32)> {
+    match event.event_type {
+        EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)) => Some((delta_x, delta_y)),
+        _ => None,
+    }
+}
+
+// Lines per millisecond. Without time in between, any distance is infinitely fast and nothing
+// stays at zero, instead of 0 / 0 coming out as NaN and failing every threshold.
+fn speed(delta: f32, duration: time::Duration) -> f32 {
+    let millis = duration.as_millis() as f32;
+    if millis > 0.0 {
+        delta / millis
+    } else if delta == 0.0 {
+        0.0
+    } else {
+        f32::INFINITY.copysign(delta)
+    }
+}
+
+fn reaches<T: PartialOrd>(value: T, threshold: T, inclusive: bool) -> bool {
+    if inclusive {
+        value >= threshold
+    } else {
+        value > threshold
+    }
+}
+
+// Result of feeding an event into the smoothing state
+enum ScrollUpdate {
+    // The event skipped the keep or drop decision, for example a boost
+    Decided((bool, f32, f32)),
+    Smoothed {
+        smoothed: (f32, f32),
+        // Lines per millisecond
+        speed: (f32, f32),
+        alpha: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollDecision {
+    Keep,
+    // Too slow, but large enough to keep anyway
+    KeepLarge,
+    Drop,
+}
+
+// The keep or drop decision, which only depends on its arguments.
+// `speed` is the smoothed speed in lines per millisecond, `raw` the deltas before smoothing.
+// The thresholds come from `tuning`, since they can change at runtime.
+fn should_keep(
+    speed: (f32, f32),
+    raw: (f32, f32),
+    tuning: &Tuning,
+    config: &EventHandlerConfig,
+) -> ScrollDecision {
+    let inclusive = config.inclusive_threshold;
+    // If the scroll is too slow, we don't want to keep the event
+    if reaches(speed.0.abs(), tuning.min_speed, inclusive)
+        || reaches(speed.1.abs(), tuning.min_speed, inclusive)
+    {
+        return ScrollDecision::Keep;
+    }
+    // Large deltas are kept no matter how slow the scroll is
+    if config.bypass_large_deltas
+        && (reaches(Lines(raw.0).abs(), tuning.always_pass_above, inclusive)
+            || reaches(Lines(raw.1).abs(), tuning.always_pass_above, inclusive))
+    {
+        return ScrollDecision::KeepLarge;
+    }
+    ScrollDecision::Drop
+}
+
+// Limits the smoothed delta to the raw one when both point in the same direction
+fn clamp_overshoot(smoothed: f32, raw: f32) -> f32 {
+    if smoothed * raw > 0.0 && smoothed.abs() > raw.abs() {
+        raw
+    } else {
+        smoothed
+    }
+}
+
+fn set_wheel_delta(event: Event, delta_x: f32, delta_y: f32) -> Event {
+    Event {
+        event_type: EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)),
+        ..event
+    }
+}
+
+// Replaces the deltas of a wheel event, or drops it if nothing is left to scroll
+fn with_wheel_delta(event: Event, delta_x: f32, delta_y: f32) -> Option<Event> {
+    if delta_x == 0.0 && delta_y == 0.0 {
+        return None;
+    }
+    Some(set_wheel_delta(event, delta_x, delta_y))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::app_config::AppConfig;
+    use crate::clock::FakeClock;
+
+    // Any fixed point in time works, only the time between events matters
+    fn start_time() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000)
+    }
+
+    fn default_config() -> EventHandlerConfig {
+        EventHandlerConfig::from(&AppConfig::default())
+    }
+
+    fn wheel_event(millis: u64, delta_x: f32, delta_y: f32) -> Event {
+        Event {
+            time: start_time() + Duration::from_millis(millis),
+            name: None,
+            event_type: EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)),
+        }
+    }
+
+    // A handler that was launched at the start time, and whose output is collected
+    struct Harness {
+        handler: EventHandler,
+        emitted: Arc<Mutex<Vec<EventType>>>,
+    }
+
+    impl Harness {
+        fn new(config: EventHandlerConfig) -> Self {
+            Self::launched_at(config, start_time())
+        }
+
+        fn launched_at(config: EventHandlerConfig, launch_time: SystemTime) -> Self {
+            let (emitter, emitted) = Emitter::buffered();
+            let clock = FakeClock::new(launch_time);
+            Harness {
+                handler: EventHandler::new(config, true, emitter).with_clock(&clock),
+                emitted,
+            }
+        }
+
+        // Feeds a wheel event `millis` after the start, and returns the deltas that are let through
+        fn scroll(&self, millis: u64, delta_x: f32, delta_y: f32) -> Option<(f32, f32)> {
+            let event = wheel_event(millis, delta_x, delta_y);
+            self.handler.callback(event).as_ref().and_then(wheel_delta)
+        }
+
+        // Events that were sent on their own, since the last call
+        fn emitted(&self) -> Vec<EventType> {
+            std::mem::take(&mut *self.emitted.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn smooths_a_scripted_gesture() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::Exponential;
+        config.time_constant = Duration::from_millis(10);
+        config.min_speed = 0.0;
+        let harness = Harness::new(config);
+
+        // The first delta seeds the smoothing, the next ones are averaged with it
+        assert_eq!(harness.scroll(0, 0.0, 1.0), Some((0.0, 1.0)));
+        let (_, second) = harness.scroll(10, 0.0, 3.0).unwrap();
+        let alpha = 1.0 - (-1.0f32).exp();
+        assert!((second - (alpha * 3.0 + (1.0 - alpha) * 1.0)).abs() < 1e-5);
+        assert!(harness.emitted().is_empty());
+    }
+
+    #[test]
+    fn soft_start_counts_from_the_clock() {
+        let soft_start_config = || {
+            let mut config = default_config();
+            config.smoothing = SmoothingAlgorithm::Exponential;
+            config.time_constant = Duration::from_millis(1000);
+            config.soft_start = Duration::from_secs(10);
+            config.min_speed = 0.0;
+            config
+        };
+
+        // Right after the launch, the time constant is still close to zero
+        let harness = Harness::new(soft_start_config());
+        harness.scroll(0, 0.0, 1.0);
+        let (_, smoothed) = harness.scroll(10, 0.0, 3.0).unwrap();
+        assert!(smoothed > 2.9, "got {}", smoothed);
+
+        // Once the soft start is over, the whole time constant applies
+        let launch_time = start_time() - Duration::from_secs(10);
+        let harness = Harness::launched_at(soft_start_config(), launch_time);
+        harness.scroll(0, 0.0, 1.0);
+        let (_, smoothed) = harness.scroll(10, 0.0, 3.0).unwrap();
+        assert!(smoothed < 1.1, "got {}", smoothed);
+    }
+
+    #[test]
+    fn drops_drift_once_it_lasted_for_the_window() {
+        let mut config = default_config();
+        config.drift_epsilon = Lines(0.01);
+        config.drift_window = Duration::from_millis(2000);
+        config.min_speed = 0.0;
+        let harness = Harness::new(config);
+
+        // A free-spinning wheel creeping along, which is still sent within the window
+        for step in 0..20 {
+            assert!(harness.scroll(step * 100, 0.0, 0.005).is_some());
+        }
+        for step in 20..40 {
+            assert_eq!(harness.scroll(step * 100, 0.0, 0.005), None);
+        }
+        assert_eq!(
+            harness.handler.dropped_deltas.lock().unwrap().delta,
+            (0.0, 0.0)
+        );
+
+        // A real scroll ends the drift
+        assert_eq!(harness.scroll(4000, 0.0, 1.0), Some((0.0, 1.0)));
+        assert!(harness.scroll(4100, 0.0, 0.005).is_some());
+    }
+
+    #[test]
+    fn carries_notches_beyond_the_cap_over() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::None;
+        config.min_speed = 0.0;
+        config.emit_notches = true;
+        config.max_notches_per_event = 3;
+        config.coalesce_output = true;
+        let harness = Harness::new(config);
+
+        // Five lines at once only send three, the other two follow with the next events
+        assert_eq!(harness.scroll(0, 0.0, 5.0), Some((0.0, 3.0)));
+        assert_eq!(harness.scroll(100, 0.0, 0.5), Some((0.0, 2.0)));
+        assert_eq!(harness.scroll(200, 0.0, 0.5), Some((0.0, 1.0)));
+        assert!(harness.emitted().is_empty());
+    }
+
+    #[test]
+    fn starts_over_after_sleep_and_resume() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::Exponential;
+        // Slow enough that even an hour long gap would leave most of the old state
+        config.time_constant = Duration::from_secs(100_000);
+        config.idle_reset = Duration::from_secs(5);
+        config.min_speed = 0.0;
+        let harness = Harness::new(config);
+
+        harness.scroll(0, 0.0, 1.0);
+        let (_, smoothed) = harness.scroll(4000, 0.0, 3.0).unwrap();
+        assert!(smoothed < 1.1, "got {}", smoothed);
+
+        // An hour later, as if the machine had been asleep in between
+        let resumed = 4000 + 60 * 60 * 1000;
+        assert_eq!(harness.scroll(resumed, 0.0, 3.0), Some((0.0, 3.0)));
+    }
+
+    #[test]
+    fn leaves_the_state_alone_during_the_warmup() {
+        let warmup_config = |warmup_action| {
+            let mut config = default_config();
+            config.smoothing = SmoothingAlgorithm::Exponential;
+            config.time_constant = Duration::from_secs(100_000);
+            config.min_speed = 0.0;
+            config.warmup_events = 3;
+            config.warmup_action = warmup_action;
+            config
+        };
+
+        let harness = Harness::new(warmup_config(WarmupAction::Pass));
+        for step in 0..3 {
+            assert_eq!(harness.scroll(step * 10, 0.0, 5.0), Some((0.0, 5.0)));
+            assert!(harness.handler.last_scroll.lock().unwrap().is_none());
+        }
+        // The first event after the warm-up is the first one the smoothing sees
+        assert_eq!(harness.scroll(30, 0.0, 1.0), Some((0.0, 1.0)));
+        let (_, smoothed) = harness.scroll(40, 0.0, 5.0).unwrap();
+        assert!(smoothed < 1.1, "got {}", smoothed);
+
+        let harness = Harness::new(warmup_config(WarmupAction::Drop));
+        for step in 0..3 {
+            assert_eq!(harness.scroll(step * 10, 0.0, 5.0), None);
+            assert!(harness.handler.last_scroll.lock().unwrap().is_none());
+        }
+        assert_eq!(harness.scroll(30, 0.0, 1.0), Some((0.0, 1.0)));
+    }
+
+    #[test]
+    fn keeps_pure_vertical_output_on_its_axis() {
+        let cross_axis_config = |suppress_cross_axis| {
+            let mut config = default_config();
+            config.smoothing = SmoothingAlgorithm::Exponential;
+            config.time_constant = Duration::from_millis(100);
+            config.min_speed = 0.0;
+            config.suppress_cross_axis = suppress_cross_axis;
+            config
+        };
+
+        // A diagonal scroll leaves some horizontal smoothing state behind
+        let harness = Harness::new(cross_axis_config(false));
+        harness.scroll(0, 1.0, 1.0);
+        let (delta_x, _) = harness.scroll(10, 0.0, 1.0).unwrap();
+        assert_ne!(delta_x, 0.0);
+
+        let harness = Harness::new(cross_axis_config(true));
+        harness.scroll(0, 1.0, 1.0);
+        for step in 1..10 {
+            let (delta_x, delta_y) = harness.scroll(step * 10, 0.0, 1.0).unwrap();
+            assert_eq!(delta_x, 0.0);
+            assert!(delta_y > 0.0);
+        }
+    }
+
+    #[test]
+    fn clamps_the_smoothed_delta_to_the_raw_one() {
+        let overshoot_config = |clamp_overshoot| {
+            let mut config = default_config();
+            config.smoothing = SmoothingAlgorithm::Exponential;
+            config.time_constant = Duration::from_millis(100);
+            config.min_speed = 0.0;
+            config.clamp_overshoot = clamp_overshoot;
+            config
+        };
+        // Slows down from fast scrolling to single lines
+        let raw = [5.0, 5.0, 1.0, 1.0, 1.0, 0.5];
+
+        let harness = Harness::new(overshoot_config(false));
+        let overshoots = raw
+            .iter()
+            .enumerate()
+            .filter(|(step, delta)| {
+                let (_, smoothed) = harness.scroll(*step as u64 * 10, 0.0, **delta).unwrap();
+                smoothed > **delta
+            })
+            .count();
+        assert!(overshoots > 0);
+
+        let harness = Harness::new(overshoot_config(true));
+        for (step, delta) in raw.iter().enumerate() {
+            let (_, smoothed) = harness.scroll(step as u64 * 10, 0.0, *delta).unwrap();
+            assert!(smoothed <= *delta, "got {} for {}", smoothed, delta);
+        }
+    }
+
+    fn threshold_config(
+        inclusive_threshold: bool,
+        bypass_large_deltas: bool,
+    ) -> EventHandlerConfig {
+        let mut config = default_config();
+        config.min_speed = 0.01;
+        config.always_pass_above = Lines(2.0);
+        config.inclusive_threshold = inclusive_threshold;
+        config.bypass_large_deltas = bypass_large_deltas;
+        config
+    }
+
+    #[test]
+    fn keeps_or_drops_by_speed_and_size() {
+        use ScrollDecision::{Drop, Keep, KeepLarge};
+
+        let config = threshold_config(true, true);
+        let fast = 0.02;
+        let slow = 0.001;
+        let large = 3.0;
+        let small = 0.5;
+        // Every combination of a fast or slow speed and a large or small delta, on either axis
+        let cases = [
+            ((fast, 0.0), (small, 0.0), Keep),
+            ((0.0, fast), (0.0, small), Keep),
+            ((-fast, 0.0), (-small, 0.0), Keep),
+            ((0.0, -fast), (0.0, large), Keep),
+            ((slow, 0.0), (large, 0.0), KeepLarge),
+            ((0.0, slow), (0.0, -large), KeepLarge),
+            ((slow, slow), (small, large), KeepLarge),
+            ((slow, 0.0), (small, 0.0), Drop),
+            ((0.0, -slow), (0.0, -small), Drop),
+            ((slow, slow), (small, small), Drop),
+            ((0.0, 0.0), (0.0, 0.0), Drop),
+        ];
+        for (speed, raw, expected) in cases {
+            assert_eq!(
+                should_keep(speed, raw, &config.tuning(), &config),
+                expected,
+                "speed {:?}, raw {:?}",
+                speed,
+                raw
+            );
+        }
+
+        // Without the bypass, only the speed counts
+        let config = threshold_config(true, false);
+        assert_eq!(
+            should_keep((slow, 0.0), (large, 0.0), &config.tuning(), &config),
+            Drop
+        );
+        assert_eq!(
+            should_keep((fast, 0.0), (small, 0.0), &config.tuning(), &config),
+            Keep
+        );
+    }
+
+    #[test]
+    fn thresholds_are_inclusive_unless_configured_otherwise() {
+        use ScrollDecision::{Drop, Keep, KeepLarge};
+
+        // Exactly at the speed threshold, with a small delta
+        let at_speed = ((0.0, 0.01), (0.0, 0.5));
+        // Exactly at the size threshold, and too slow. Wheel units of 1/120 lines land on it exactly.
+        let at_size = ((0.0, 0.001), (0.0, 240.0 / 120.0));
+
+        let config = threshold_config(true, true);
+        assert_eq!(
+            should_keep(at_speed.0, at_speed.1, &config.tuning(), &config),
+            Keep
+        );
+        assert_eq!(
+            should_keep(at_size.0, at_size.1, &config.tuning(), &config),
+            KeepLarge
+        );
+
+        let config = threshold_config(false, true);
+        assert_eq!(
+            should_keep(at_speed.0, at_speed.1, &config.tuning(), &config),
+            Drop
+        );
+        assert_eq!(
+            should_keep(at_size.0, at_size.1, &config.tuning(), &config),
+            Drop
+        );
+    }
+
+    #[test]
+    fn repeats_outputs_that_barely_changed() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::None;
+        config.min_speed = 0.0;
+        config.output_jitter_epsilon = Lines(0.005);
+        let harness = Harness::new(config);
+
+        // A steady scroll that wobbles around the same delta
+        for (step, delta) in [0.031, 0.029, 0.031, 0.029, 0.032].iter().enumerate() {
+            assert_eq!(
+                harness.scroll(step as u64 * 10, 0.0, *delta),
+                Some((0.0, 0.031))
+            );
+        }
+        // Real changes still come through
+        assert_eq!(harness.scroll(50, 0.0, 0.05), Some((0.0, 0.05)));
+        assert_eq!(harness.scroll(60, 0.0, -0.05), Some((0.0, -0.05)));
+    }
+
+    #[test]
+    fn drops_duplicated_events() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::None;
+        config.min_speed = 0.0;
+        config.duplicate_window = Duration::from_millis(2);
+        let harness = Harness::new(config);
+
+        // A driver that reports every notch twice
+        assert_eq!(harness.scroll(0, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(harness.scroll(1, 0.0, 1.0), None);
+        assert_eq!(harness.scroll(10, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(harness.scroll(11, 0.0, 1.0), None);
+        // Another delta within the window is a scroll of its own
+        assert_eq!(harness.scroll(12, 0.0, 2.0), Some((0.0, 2.0)));
+        assert_eq!(harness.handler.counters.dropped.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn passes_its_own_events_through_untouched() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::Exponential;
+        config.time_constant = Duration::from_secs(100_000);
+        config.min_speed = 0.0;
+        config.emit_notches = true;
+        config.max_notches_per_event = 3;
+        let (emitter, sent) = Emitter::loopback(Duration::from_secs(1));
+        let handler = EventHandler::new(config, true, emitter);
+
+        // Three notches at once, two of which are sent on their own
+        let output = handler.callback(wheel_event(0, 0.0, 3.0));
+        assert_eq!(output.as_ref().and_then(wheel_delta), Some((0.0, 1.0)));
+        let sent = std::mem::take(&mut *sent.lock().unwrap());
+        assert_eq!(sent.len(), 2);
+
+        // The hook delivers them again, as if they came from the mouse
+        for event_type in sent {
+            let event = Event {
+                event_type,
+                ..wheel_event(1, 0.0, 0.0)
+            };
+            let output = handler.callback(event);
+            assert_eq!(output.as_ref().and_then(wheel_delta), Some((0.0, 1.0)));
+        }
+        let last_scroll = handler.last_scroll.lock().unwrap();
+        assert_eq!(last_scroll.as_ref().unwrap().timestamp, start_time());
+        assert_eq!(handler.counters.processed.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn stops_a_gesture_at_max_gesture_distance() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::None;
+        config.min_speed = 0.0;
+        config.max_gesture_distance = Lines(10.0);
+        config.idle_reset = Duration::from_secs(1);
+        let harness = Harness::new(config);
+
+        // A free-spinning wheel, three lines every 10 milliseconds
+        let sent: Vec<f32> = (0..10)
+            .filter_map(|step| harness.scroll(step * 10, 0.0, 3.0))
+            .map(|(_, delta_y)| delta_y)
+            .collect();
+        assert_eq!(sent, [3.0, 3.0, 3.0, 1.0]);
+
+        // The next gesture starts from zero again
+        assert_eq!(harness.scroll(5000, 0.0, 3.0), Some((0.0, 3.0)));
+    }
+
+    #[test]
+    fn drops_scrolling_right_after_a_click() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::Exponential;
+        config.time_constant = Duration::from_secs(100_000);
+        config.min_speed = 0.0;
+        config.click_scroll_cooldown = Duration::from_millis(100);
+        let harness = Harness::new(config);
+
+        harness.scroll(0, 0.0, 1.0);
+        harness.scroll(10, 0.0, 1.0);
+        let click = Event {
+            event_type: EventType::ButtonPress(rdev::Button::Middle),
+            ..wheel_event(20, 0.0, 0.0)
+        };
+        harness.handler.callback(click);
+
+        // The wheel wobbles while the button goes down
+        assert_eq!(harness.scroll(25, 0.0, 0.2), None);
+        assert_eq!(harness.scroll(40, 0.0, -0.1), None);
+        assert_eq!(harness.scroll(110, 0.0, 0.2), None);
+        // Once the cooldown is over, scrolling starts over without the old smoothing
+        assert_eq!(harness.scroll(130, 0.0, 3.0), Some((0.0, 3.0)));
+    }
+
+    #[test]
+    fn large_deltas_only_skip_the_speed_check_with_the_bypass() {
+        let bypass_config = |bypass_large_deltas| {
+            let mut config = default_config();
+            config.smoothing = SmoothingAlgorithm::None;
+            // Nothing is fast enough on its own
+            config.min_speed = 1.0;
+            config.always_pass_above = Lines(2.0);
+            config.bypass_large_deltas = bypass_large_deltas;
+            config
+        };
+
+        let harness = Harness::new(bypass_config(true));
+        assert_eq!(harness.scroll(0, 0.0, 3.0), Some((0.0, 3.0)));
+        assert_eq!(harness.scroll(100, 0.0, 3.0), Some((0.0, 3.0)));
+        assert_eq!(harness.scroll(200, 0.0, 1.0), None);
+
+        let harness = Harness::new(bypass_config(false));
+        assert_eq!(harness.scroll(0, 0.0, 3.0), None);
+        assert_eq!(harness.scroll(100, 0.0, 3.0), None);
+        assert_eq!(harness.scroll(200, 0.0, 1.0), None);
+    }
+
+    #[test]
+    fn boosts_only_after_a_measured_pause() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::None;
+        config.min_speed = 0.0;
+        config.boost_window = Duration::from_millis(100);
+        config.boost_multiplier = 3.0;
+        config.boost_min_distance = Lines(1.0);
+        config.idle_reset = Duration::from_secs(5);
+        let harness = Harness::new(config);
+
+        // Right after the launch, the first tick has no pause to measure
+        assert_eq!(harness.scroll(0, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(harness.scroll(50, 0.0, 1.0), Some((0.0, 1.0)));
+        // A double tap after a pause
+        assert_eq!(harness.scroll(1000, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(harness.scroll(1050, 0.0, 1.0), Some((0.0, 3.0)));
+        // Continuing to scroll doesn't
+        assert_eq!(harness.scroll(1100, 0.0, 1.0), Some((0.0, 1.0)));
+        // Neither does the first double tap after an idle reset
+        assert_eq!(harness.scroll(10_000, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(harness.scroll(10_050, 0.0, 1.0), Some((0.0, 1.0)));
+    }
+
+    #[test]
+    fn keeps_events_with_the_same_timestamp() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::Exponential;
+        config.time_constant = Duration::from_millis(10);
+        config.min_speed = 0.005;
+        let harness = Harness::new(config);
+
+        harness.scroll(0, 0.0, 1.0);
+        let previous = harness.scroll(10, 0.0, 1.0).unwrap();
+        // A high polling rate delivers another event within the same millisecond. It is kept,
+        // and gets no weight in the smoothing.
+        assert_eq!(harness.scroll(10, 0.0, 2.0), Some(previous));
+        assert_eq!(harness.handler.diagnostics.last_alpha(), 0.0);
+        assert!(harness.handler.last_velocity.lock().unwrap().1 > 0.0);
+    }
+
+    #[test]
+    fn conserving_smoothing_sends_the_whole_distance() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::Conserving;
+        config.time_constant = Duration::from_millis(50);
+        config.min_speed = 0.005;
+        config.always_pass_above = Lines(0.5);
+        config.flush_dropped_after = Duration::ZERO;
+        config.idle_reset = Duration::from_secs(5);
+        let harness = Harness::new(config);
+
+        // A quick start, then a slow tick that is dropped and held back, then a pause long
+        // enough for an idle reset before the next gesture
+        let input = [(0, 1.0), (10, 1.0), (20, 1.0), (2000, 0.2), (10_000, 1.0)];
+        let mut sent = 0.0;
+        for (millis, delta_y) in input {
+            if let Some((_, output)) = harness.scroll(millis, 0.0, delta_y) {
+                sent += output;
+            }
+            for event_type in harness.emitted() {
+                if let EventType::Wheel(MouseScrollDelta::LineDelta(_, output)) = event_type {
+                    sent += output;
+                }
+            }
+        }
+        let total: f32 = input.iter().map(|(_, delta_y)| delta_y).sum();
+        assert!((sent - total).abs() < 1e-4, "sent {} of {}", sent, total);
+        assert_eq!(
+            harness.handler.filter.lock().unwrap().take_backlog(),
+            (0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn applies_a_new_tuning_right_away() {
+        let mut config = default_config();
+        config.smoothing = SmoothingAlgorithm::None;
+        config.min_speed = 0.0;
+        let harness = Harness::new(config);
+        assert_eq!(harness.scroll(0, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(harness.scroll(100, 0.0, 1.0), Some((0.0, 1.0)));
+
+        // One line in 100 milliseconds is now too slow
+        harness.handler.controls().set_tuning(Tuning {
+            time_constant: Duration::ZERO,
+            min_speed: 0.1,
+            always_pass_above: Lines(2.0),
+        });
+        assert_eq!(harness.scroll(200, 0.0, 1.0), None);
+        assert_eq!(harness.scroll(300, 0.0, 3.0), Some((0.0, 3.0)));
+    }
+
+    #[test]
+    fn scales_the_thresholds_by_the_speed_multiplier_if_asked_to() {
+        for scale_thresholds in [false, true] {
+            let mut config = threshold_config(true, true);
+            config.profiles = vec![Profile {
+                name: "Fast".to_string(),
+                speed_multiplier: Some(4.0),
+                ..Profile::default()
+            }];
+            config.scale_thresholds_by_multiplier = scale_thresholds;
+            config.passthrough_first_tick = true;
+            let harness = Harness::new(config);
+            harness
+                .handler
+                .controls()
+                .set_profile_override(Some("Fast".to_string()));
+            assert_eq!(harness.scroll(0, 0.0, 1.0), Some((0.0, 4.0)));
+
+            let thresholds = harness.handler.thresholds();
+            let factor = if scale_thresholds { 4.0 } else { 1.0 };
+            assert_eq!(thresholds.min_speed, 0.01 * factor);
+            assert_eq!(thresholds.always_pass_above, Lines(2.0 * factor));
+        }
+    }
+
+    #[test]
+    fn seeds_the_smoothing_by_the_cold_start() {
+        let cold_start_config = |cold_start| {
+            let mut config = default_config();
+            config.smoothing = SmoothingAlgorithm::Exponential;
+            config.time_constant = Duration::from_millis(30);
+            config.min_speed = 0.0;
+            config.cold_start = cold_start;
+            config
+        };
+        let alpha = 1.0 - (-1.0f32).exp();
+        // Scrolling down first is no reversal, since there was no direction before
+        for delta in [2.0, -2.0] {
+            let harness = Harness::new(cold_start_config(ColdStart::FirstDelta));
+            assert_eq!(harness.scroll(0, 0.0, delta), Some((0.0, delta)));
+
+            let harness = Harness::new(cold_start_config(ColdStart::Zero));
+            let (_, first) = harness.scroll(0, 0.0, delta).unwrap();
+            assert!((first - alpha * delta).abs() < 1e-5, "got {}", first);
+            // The ramp goes on from there
+            let (_, second) = harness.scroll(30, 0.0, delta).unwrap();
+            assert!((second - (alpha * delta + (1.0 - alpha) * first)).abs() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::headless::parse_script;
+
+// Scripts and recordings are lists of `(milliseconds since start, delta_x, delta_y)`
+pub type ScriptLine = (u64, f32, f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventLogFormat {
+    // One `<milliseconds> <delta_x> <delta_y>` line per event
+    Text,
+    // Compact records, for long recordings of high polling rate mice
+    Binary,
+}
+
+// The binary format starts with this, followed by records of a length byte and that many bytes.
+// A record is the milliseconds since the previous record, delta_x and delta_y as little endian f32.
+// Readers skip any bytes past the ones they know, so records can grow later on.
+const MAGIC: &[u8; 4] = b"SSL1";
+const RECORD_LENGTH: u8 = 12;
+
+// Reads either format, binary logs are recognized by their header
+pub fn read_event_log(path: &Path) -> Result<Vec<ScriptLine>, Box<dyn std::error::Error>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(MAGIC) {
+        Ok(decode_binary(&bytes[MAGIC.len()..])?)
+    } else {
+        Ok(parse_script(std::str::from_utf8(&bytes)?)?)
+    }
+}
+
+pub fn write_event_log(
+    path: &Path,
+    lines: &[ScriptLine],
+    format: EventLogFormat,
+) -> std::io::Result<()> {
+    let bytes = match format {
+        EventLogFormat::Text => format_text(lines).into_bytes(),
+        EventLogFormat::Binary => encode_binary(lines),
+    };
+    fs::write(path, bytes)
+}
+
+fn format_text(lines: &[ScriptLine]) -> String {
+    let mut script =
+        String::from("# Recorded scroll gesture: <milliseconds> <delta_x> <delta_y>\n");
+    for (millis, delta_x, delta_y) in lines {
+        script.push_str(&format!("{} {} {}\n", millis, delta_x, delta_y));
+    }
+    script
+}
+
+fn encode_binary(lines: &[ScriptLine]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(MAGIC.len() + lines.len() * (1 + RECORD_LENGTH as usize));
+    bytes.extend_from_slice(MAGIC);
+    let mut last_millis = 0;
+    for &(millis, delta_x, delta_y) in lines {
+        let elapsed = millis.saturating_sub(last_millis) as f32;
+        last_millis = millis;
+        bytes.push(RECORD_LENGTH);
+        bytes.extend_from_slice(&elapsed.to_le_bytes());
+        bytes.extend_from_slice(&delta_x.to_le_bytes());
+        bytes.extend_from_slice(&delta_y.to_le_bytes());
+    }
+    bytes
+}
+
+fn decode_binary(mut bytes: &[u8]) -> Result<Vec<ScriptLine>, String> {
+    let mut lines = Vec::new();
+    let mut millis = 0.0_f64;
+    while let Some((&length, rest)) = bytes.split_first() {
+        let length = length as usize;
+        if length < RECORD_LENGTH as usize || rest.len() < length {
+            return Err(format!("Invalid event log record {}", lines.len() + 1));
+        }
+        let value = |index: usize| {
+            let start = index * 4;
+            f32::from_le_bytes([
+                rest[start],
+                rest[start + 1],
+                rest[start + 2],
+                rest[start + 3],
+            ])
+        };
+        millis += value(0) as f64;
+        lines.push((millis.round() as u64, value(1), value(2)));
+        bytes = &rest[length..];
+    }
+    Ok(lines)
+}
@@ -0,0 +1,584 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmoothingAlgorithm {
+    // Only drops events, the kept ones are sent as they are
+    None,
+    Exponential,
+    // Spreads every delta out over time, but sends exactly the distance that came in
+    Conserving,
+}
+
+// Which axes count as reversed when a diagonal scroll changes direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReversalPolicy {
+    // A flip on either axis resets both
+    AnyAxis,
+    // Only the axis that flipped is reset
+    PerAxis,
+    // Only a flip on the axis with the larger delta counts, and resets both
+    DominantOnly,
+}
+
+impl ReversalPolicy {
+    // Returns which axes reversed, given which of them flipped their sign
+    fn reversed(self, flipped: (bool, bool), delta_x: f32, delta_y: f32) -> (bool, bool) {
+        match self {
+            ReversalPolicy::AnyAxis => {
+                let any = flipped.0 || flipped.1;
+                (any, any)
+            }
+            ReversalPolicy::PerAxis => flipped,
+            ReversalPolicy::DominantOnly => {
+                let dominant = if delta_x.abs() > delta_y.abs() {
+                    flipped.0
+                } else {
+                    flipped.1
+                };
+                (dominant, dominant)
+            }
+        }
+    }
+}
+
+// Shapes the scroll deltas before the keep or drop decision
+pub trait ScrollFilter: Send {
+    // `dt` is the time since the previous scroll event
+    fn apply(&mut self, delta_x: f32, delta_y: f32, dt: Duration) -> (f32, f32);
+
+    // Forgets everything, for example after an idle reset
+    fn reset(&mut self);
+
+    // Used by the soft start, filters without a time constant ignore it
+    fn set_time_constant(&mut self, _time_constant: Duration) {}
+
+    // Weight of the newest delta in the last output, for diagnostics
+    fn last_alpha(&self) -> f32 {
+        1.0
+    }
+
+    // Takes back output that was dropped instead of sent. Returns false if the filter
+    // doesn't keep track of its distance, and the caller has to count it as dropped.
+    fn hold_back(&mut self, _delta_x: f32, _delta_y: f32) -> bool {
+        false
+    }
+
+    // Distance that was taken in, but hasn't been sent yet
+    fn take_backlog(&mut self) -> (f32, f32) {
+        (0.0, 0.0)
+    }
+}
+
+pub type SharedFilter = Arc<Mutex<Box<dyn ScrollFilter>>>;
+
+pub fn create_filter(
+    algorithm: SmoothingAlgorithm,
+    time_constant: Duration,
+    reversal_cushion: Duration,
+    reversal_policy: ReversalPolicy,
+    sign_neutral_zone: f32,
+    clamp_zero_crossing: bool,
+) -> Box<dyn ScrollFilter> {
+    match algorithm {
+        SmoothingAlgorithm::None => Box::new(PassThroughFilter),
+        SmoothingAlgorithm::Exponential => Box::new(ExponentialFilter::new(
+            time_constant,
+            reversal_cushion,
+            reversal_policy,
+            sign_neutral_zone,
+            clamp_zero_crossing,
+        )),
+        SmoothingAlgorithm::Conserving => Box::new(ConservingFilter::new(time_constant)),
+    }
+}
+
+// Algorithm and time constant of one axis, when the axes are smoothed separately
+#[derive(Debug, Clone, Copy)]
+pub struct AxisSmoothing {
+    pub algorithm: SmoothingAlgorithm,
+    pub time_constant: Duration,
+}
+
+// `time_constant` is the global one, which the soft start scales the axes by
+pub fn create_axis_filter(
+    x: AxisSmoothing,
+    y: AxisSmoothing,
+    time_constant: Duration,
+    reversal_cushion: Duration,
+    sign_neutral_zone: f32,
+    clamp_zero_crossing: bool,
+) -> Box<dyn ScrollFilter> {
+    // Each filter only ever sees one axis, so any policy comes down to a per axis reversal
+    let create = |axis: AxisSmoothing| {
+        create_filter(
+            axis.algorithm,
+            axis.time_constant,
+            reversal_cushion,
+            ReversalPolicy::PerAxis,
+            sign_neutral_zone,
+            clamp_zero_crossing,
+        )
+    };
+    Box::new(AxisFilter {
+        x: create(x),
+        y: create(y),
+        time_constants: (x.time_constant, y.time_constant),
+        time_constant,
+    })
+}
+
+pub struct PassThroughFilter;
+
+impl ScrollFilter for PassThroughFilter {
+    fn apply(&mut self, delta_x: f32, delta_y: f32, _dt: Duration) -> (f32, f32) {
+        (delta_x, delta_y)
+    }
+
+    fn reset(&mut self) {}
+}
+
+// Weight of the new delta in the exponential moving average, with the time constant in seconds.
+// Goes from 0.0 for no elapsed time towards 1.0 once `dt` is much longer than the time constant.
+pub fn compute_alpha(dt: Duration, time_constant: f32, sign_changed: bool) -> f32 {
+    if sign_changed || time_constant <= 0.0 {
+        return 1.0;
+    }
+    (1.0 - (-dt.as_secs_f32() / time_constant).exp()).clamp(0.0, 1.0)
+}
+
+// Deltas inside the neutral zone have no sign, so that noise around zero never counts as a reversal.
+// Zero has no sign either, which is also what a fresh state starts with.
+fn has_flipped(last_delta: f32, delta: f32, neutral_zone: f32) -> bool {
+    if last_delta == 0.0 || delta == 0.0 {
+        false
+    } else if neutral_zone > 0.0 {
+        last_delta.abs() > neutral_zone
+            && delta.abs() > neutral_zone
+            && last_delta.signum() != delta.signum()
+    } else {
+        last_delta.signum() != delta.signum()
+    }
+}
+
+// Without a reversal, a fading output can only end at zero. Small opposite deltas that don't
+// count as a reversal could otherwise pull it past zero, and scroll back a little at the end.
+fn stop_at_zero(previous: f32, smoothed: f32, reversed: bool) -> f32 {
+    if !reversed && previous * smoothed < 0.0 {
+        0.0
+    } else {
+        smoothed
+    }
+}
+
+// Old direction output that is faded out after a reversal
+struct Cushion {
+    carry: (f32, f32),
+    elapsed: Duration,
+}
+
+// Exponential moving average, where older deltas fade out with the time constant.
+// A change of direction skips the smoothing, so that reversing the wheel is instant.
+// With a reversal cushion, the old direction fades out over the cushion instead,
+// while the new direction builds up from zero.
+pub struct ExponentialFilter {
+    time_constant: Duration,
+    reversal_cushion: Duration,
+    reversal_policy: ReversalPolicy,
+    sign_neutral_zone: f32,
+    clamp_zero_crossing: bool,
+    smoothed: (f32, f32),
+    // Last delta outside of the neutral zone on each axis, zero before there was one
+    last_delta: (f32, f32),
+    last_alpha: f32,
+    cushion: Option<Cushion>,
+}
+
+impl ExponentialFilter {
+    pub fn new(
+        time_constant: Duration,
+        reversal_cushion: Duration,
+        reversal_policy: ReversalPolicy,
+        sign_neutral_zone: f32,
+        clamp_zero_crossing: bool,
+    ) -> Self {
+        ExponentialFilter {
+            time_constant,
+            reversal_cushion,
+            reversal_policy,
+            sign_neutral_zone,
+            clamp_zero_crossing,
+            smoothed: (0.0, 0.0),
+            last_delta: (0.0, 0.0),
+            last_alpha: 1.0,
+            cushion: None,
+        }
+    }
+}
+
+impl ScrollFilter for ExponentialFilter {
+    fn apply(&mut self, delta_x: f32, delta_y: f32, dt: Duration) -> (f32, f32) {
+        let zone = self.sign_neutral_zone;
+        let flipped = (
+            has_flipped(self.last_delta.0, delta_x, zone),
+            has_flipped(self.last_delta.1, delta_y, zone),
+        );
+        let mut reversed = self.reversal_policy.reversed(flipped, delta_x, delta_y);
+        if delta_x.abs() > zone {
+            self.last_delta.0 = delta_x;
+        }
+        if delta_y.abs() > zone {
+            self.last_delta.1 = delta_y;
+        }
+
+        if (reversed.0 || reversed.1)
+            && !self.reversal_cushion.is_zero()
+            && self.smoothed != (0.0, 0.0)
+        {
+            // Only the reversed axes fade out, the others keep their smoothing
+            let carry = (
+                if reversed.0 { self.smoothed.0 } else { 0.0 },
+                if reversed.1 { self.smoothed.1 } else { 0.0 },
+            );
+            self.cushion = Some(Cushion {
+                carry,
+                elapsed: Duration::ZERO,
+            });
+            self.smoothed = (self.smoothed.0 - carry.0, self.smoothed.1 - carry.1);
+            reversed = (false, false);
+        }
+
+        let time_constant = self.time_constant.as_secs_f32();
+        let alpha_x = compute_alpha(dt, time_constant, reversed.0);
+        let alpha_y = compute_alpha(dt, time_constant, reversed.1);
+        self.last_alpha = alpha_x.max(alpha_y);
+
+        let previous = self.smoothed;
+        self.smoothed = (
+            alpha_x * delta_x + (1.0 - alpha_x) * previous.0,
+            alpha_y * delta_y + (1.0 - alpha_y) * previous.1,
+        );
+        if self.clamp_zero_crossing {
+            self.smoothed = (
+                stop_at_zero(previous.0, self.smoothed.0, reversed.0),
+                stop_at_zero(previous.1, self.smoothed.1, reversed.1),
+            );
+        }
+
+        let Some(cushion) = self.cushion.as_mut() else {
+            return self.smoothed;
+        };
+        cushion.elapsed += dt;
+        let fade = 1.0 - cushion.elapsed.as_secs_f32() / self.reversal_cushion.as_secs_f32();
+        if fade <= 0.0 {
+            self.cushion = None;
+            return self.smoothed;
+        }
+        (
+            self.smoothed.0 + fade * cushion.carry.0,
+            self.smoothed.1 + fade * cushion.carry.1,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.smoothed = (0.0, 0.0);
+        self.last_delta = (0.0, 0.0);
+        self.cushion = None;
+    }
+
+    fn set_time_constant(&mut self, time_constant: Duration) {
+        self.time_constant = time_constant;
+    }
+
+    fn last_alpha(&self) -> f32 {
+        self.last_alpha
+    }
+}
+
+// Runs each axis through a filter of its own, so that they can use different algorithms
+pub struct AxisFilter {
+    x: Box<dyn ScrollFilter>,
+    y: Box<dyn ScrollFilter>,
+    // Configured per axis, and globally
+    time_constants: (Duration, Duration),
+    time_constant: Duration,
+}
+
+impl ScrollFilter for AxisFilter {
+    fn apply(&mut self, delta_x: f32, delta_y: f32, dt: Duration) -> (f32, f32) {
+        let (smoothed_x, _) = self.x.apply(delta_x, 0.0, dt);
+        let (_, smoothed_y) = self.y.apply(0.0, delta_y, dt);
+        (smoothed_x, smoothed_y)
+    }
+
+    fn reset(&mut self) {
+        self.x.reset();
+        self.y.reset();
+    }
+
+    // The soft start ramps up the global time constant, and each axis follows at the same pace
+    fn set_time_constant(&mut self, time_constant: Duration) {
+        if self.time_constant.is_zero() {
+            return;
+        }
+        let progress = time_constant.as_secs_f32() / self.time_constant.as_secs_f32();
+        self.x
+            .set_time_constant(self.time_constants.0.mul_f32(progress));
+        self.y
+            .set_time_constant(self.time_constants.1.mul_f32(progress));
+    }
+
+    fn last_alpha(&self) -> f32 {
+        self.x.last_alpha().max(self.y.last_alpha())
+    }
+
+    fn hold_back(&mut self, delta_x: f32, delta_y: f32) -> bool {
+        // The caller counts either both axes or neither as dropped, so a filter that took its share
+        // back gives it up again if the other one didn't
+        let held_x = self.x.hold_back(delta_x, 0.0);
+        let held_y = self.y.hold_back(0.0, delta_y);
+        if held_x && !held_y {
+            self.x.hold_back(-delta_x, 0.0);
+        } else if held_y && !held_x {
+            self.y.hold_back(0.0, -delta_y);
+        }
+        held_x && held_y
+    }
+
+    fn take_backlog(&mut self) -> (f32, f32) {
+        let (backlog_x, _) = self.x.take_backlog();
+        let (_, backlog_y) = self.y.take_backlog();
+        (backlog_x, backlog_y)
+    }
+}
+
+// Every delta goes into a backlog, and each event sends a share of it that grows with the time
+// since the previous event. Unlike the exponential filter, nothing fades away, so the sum of the
+// output equals the sum of the input once the backlog has been flushed at the end of a gesture.
+pub struct ConservingFilter {
+    time_constant: Duration,
+    backlog: (f32, f32),
+    last_alpha: f32,
+}
+
+impl ConservingFilter {
+    pub fn new(time_constant: Duration) -> Self {
+        ConservingFilter {
+            time_constant,
+            backlog: (0.0, 0.0),
+            last_alpha: 1.0,
+        }
+    }
+}
+
+impl ScrollFilter for ConservingFilter {
+    fn apply(&mut self, delta_x: f32, delta_y: f32, dt: Duration) -> (f32, f32) {
+        self.backlog = (self.backlog.0 + delta_x, self.backlog.1 + delta_y);
+        let alpha = compute_alpha(dt, self.time_constant.as_secs_f32(), false);
+        self.last_alpha = alpha;
+
+        let output = (alpha * self.backlog.0, alpha * self.backlog.1);
+        self.backlog = (self.backlog.0 - output.0, self.backlog.1 - output.1);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.backlog = (0.0, 0.0);
+    }
+
+    fn set_time_constant(&mut self, time_constant: Duration) {
+        self.time_constant = time_constant;
+    }
+
+    fn last_alpha(&self) -> f32 {
+        self.last_alpha
+    }
+
+    fn hold_back(&mut self, delta_x: f32, delta_y: f32) -> bool {
+        self.backlog = (self.backlog.0 + delta_x, self.backlog.1 + delta_y);
+        true
+    }
+
+    fn take_backlog(&mut self) -> (f32, f32) {
+        std::mem::take(&mut self.backlog)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_grows_with_the_elapsed_time() {
+        assert_eq!(compute_alpha(Duration::ZERO, 0.1, false), 0.0);
+        let one_time_constant = compute_alpha(Duration::from_millis(100), 0.1, false);
+        assert!((one_time_constant - (1.0 - (-1.0f32).exp())).abs() < 1e-6);
+        assert_eq!(compute_alpha(Duration::from_secs(3600), 0.1, false), 1.0);
+        assert_eq!(compute_alpha(Duration::MAX, 0.1, false), 1.0);
+    }
+
+    #[test]
+    fn alpha_without_a_time_constant_follows_the_input() {
+        assert_eq!(compute_alpha(Duration::from_millis(10), 0.0, false), 1.0);
+        assert_eq!(compute_alpha(Duration::from_millis(10), -1.0, false), 1.0);
+        let tiny = compute_alpha(Duration::from_millis(10), f32::MIN_POSITIVE, false);
+        assert_eq!(tiny, 1.0);
+        assert!(tiny.is_finite());
+    }
+
+    #[test]
+    fn alpha_of_a_reversal_drops_the_old_state() {
+        assert_eq!(compute_alpha(Duration::ZERO, 0.1, true), 1.0);
+        assert_eq!(compute_alpha(Duration::from_millis(10), 0.1, true), 1.0);
+    }
+
+    // Scrolls diagonally down and right, and then by `reversal` right after
+    fn reverse_diagonally(policy: ReversalPolicy, reversal: (f32, f32)) -> (f32, f32) {
+        let slow = Duration::from_secs(100);
+        let mut filter = ExponentialFilter::new(slow, Duration::ZERO, policy, 0.0, false);
+        assert_eq!(filter.apply(2.0, 1.0, Duration::MAX), (2.0, 1.0));
+        filter.apply(reversal.0, reversal.1, Duration::from_millis(10))
+    }
+
+    fn is_close(actual: f32, expected: f32) -> bool {
+        (actual - expected).abs() < 0.01
+    }
+
+    #[test]
+    fn any_axis_reversal_resets_both_axes() {
+        assert_eq!(
+            reverse_diagonally(ReversalPolicy::AnyAxis, (-0.5, 2.0)),
+            (-0.5, 2.0)
+        );
+    }
+
+    #[test]
+    fn per_axis_reversal_only_resets_the_flipped_axis() {
+        let (smoothed_x, smoothed_y) = reverse_diagonally(ReversalPolicy::PerAxis, (-0.5, 2.0));
+        assert_eq!(smoothed_x, -0.5);
+        assert!(is_close(smoothed_y, 1.0), "got {}", smoothed_y);
+    }
+
+    #[test]
+    fn dominant_only_reversal_follows_the_larger_axis() {
+        // The smaller axis flipping doesn't count
+        let (smoothed_x, smoothed_y) =
+            reverse_diagonally(ReversalPolicy::DominantOnly, (-0.5, 2.0));
+        assert!(is_close(smoothed_x, 2.0), "got {}", smoothed_x);
+        assert!(is_close(smoothed_y, 1.0), "got {}", smoothed_y);
+
+        // The larger axis flipping resets both
+        assert_eq!(
+            reverse_diagonally(ReversalPolicy::DominantOnly, (-3.0, 2.0)),
+            (-3.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn noise_inside_the_neutral_zone_is_no_reversal() {
+        let noisy_stream = |sign_neutral_zone| {
+            let slow = Duration::from_secs(100);
+            let policy = ReversalPolicy::AnyAxis;
+            let mut filter =
+                ExponentialFilter::new(slow, Duration::ZERO, policy, sign_neutral_zone, false);
+            filter.apply(0.0, 1.0, Duration::MAX);
+            // Counts the events that reset the smoothing
+            (0..20)
+                .filter(|step| {
+                    let noise = if step % 2 == 0 { -0.05 } else { 0.05 };
+                    filter.apply(0.0, noise, Duration::from_millis(10));
+                    filter.last_alpha() == 1.0
+                })
+                .count()
+        };
+
+        assert!(noisy_stream(0.0) >= 10);
+        assert_eq!(noisy_stream(0.1), 0);
+    }
+
+    #[test]
+    fn reversal_outside_the_neutral_zone_still_counts() {
+        let slow = Duration::from_secs(100);
+        let mut filter =
+            ExponentialFilter::new(slow, Duration::ZERO, ReversalPolicy::AnyAxis, 0.1, false);
+        filter.apply(0.0, 1.0, Duration::MAX);
+        filter.apply(0.0, -0.05, Duration::from_millis(10));
+        assert_eq!(
+            filter.apply(0.0, -1.0, Duration::from_millis(10)),
+            (0.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn decay_stops_at_zero() {
+        let decay = |clamp_zero_crossing| {
+            let time_constant = Duration::from_millis(10);
+            let policy = ReversalPolicy::AnyAxis;
+            let mut filter = ExponentialFilter::new(
+                time_constant,
+                Duration::ZERO,
+                policy,
+                0.1,
+                clamp_zero_crossing,
+            );
+            filter.apply(0.0, 1.0, Duration::MAX);
+            // The wheel was let go, and fades out. Then a tiny tick inside the neutral zone
+            // nudges it past zero, without being a reversal.
+            let mut outputs: Vec<f32> = (0..2)
+                .map(|_| filter.apply(0.0, 0.0, Duration::from_millis(50)).1)
+                .collect();
+            outputs.push(filter.apply(0.0, -0.05, Duration::from_millis(50)).1);
+            outputs
+        };
+
+        assert!(decay(false).iter().any(|smoothed| *smoothed < 0.0));
+        let outputs = decay(true);
+        assert!(
+            outputs.iter().all(|smoothed| *smoothed >= 0.0),
+            "got {:?}",
+            outputs
+        );
+        assert_eq!(outputs.last(), Some(&0.0));
+    }
+
+    #[test]
+    fn a_fresh_state_has_no_direction_to_reverse() {
+        let time_constant = Duration::from_millis(10);
+        let alpha = 1.0 - (-1.0f32).exp();
+        let mut filter = ExponentialFilter::new(
+            time_constant,
+            Duration::ZERO,
+            ReversalPolicy::AnyAxis,
+            0.0,
+            false,
+        );
+        for _ in 0..2 {
+            let (smoothed_x, smoothed_y) = filter.apply(0.0, -1.0, time_constant);
+            assert_eq!(smoothed_x, 0.0);
+            assert!(is_close(smoothed_y, -alpha));
+            assert!(filter.last_alpha() < 1.0);
+            filter.reset();
+        }
+    }
+
+    #[test]
+    fn an_axis_at_rest_has_no_direction_to_reverse() {
+        let time_constant = Duration::from_millis(10);
+        let mut filter = ExponentialFilter::new(
+            time_constant,
+            Duration::ZERO,
+            ReversalPolicy::AnyAxis,
+            0.0,
+            false,
+        );
+        filter.apply(-1.0, 1.0, Duration::MAX);
+        filter.apply(0.0, 1.0, time_constant);
+        assert!(filter.last_alpha() < 1.0);
+    }
+}
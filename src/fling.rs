@@ -0,0 +1,243 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rdev::{EventType, MouseScrollDelta};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::emitter::Emitter;
+
+// Roughly one frame at 60 Hz
+const TICK: Duration = Duration::from_millis(16);
+// How many time constants of the exponential decay fit into the fling duration
+const EXPONENTIAL_STEEPNESS: f32 = 5.0;
+// Once this little is left on an axis, it is sent in one go and the axis stops
+const STOP_DISTANCE: f32 = 0.01;
+
+// How the speed of a fling falls off over its duration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlingCurve {
+    // Fast at first, with a long tail
+    Exponential,
+    // The speed falls at a steady rate
+    Linear,
+    // Starts a little slower, keeps its speed for longer, then slows down late
+    EaseOut,
+}
+
+impl FlingCurve {
+    // Share of the distance that has been covered after `t` of the duration, both from 0 to 1.
+    // Every curve covers the whole distance at the end of the duration.
+    fn progress(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            FlingCurve::Exponential => {
+                (1.0 - (-EXPONENTIAL_STEEPNESS * t).exp()) / (1.0 - (-EXPONENTIAL_STEEPNESS).exp())
+            }
+            FlingCurve::Linear => 1.0 - (1.0 - t).powi(2),
+            // The speed falls like 1 - t², which stays high at first and drops towards the end
+            FlingCurve::EaseOut => (3.0 * t - t.powi(3)) / 2.0,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FlingState {
+    // Every flick starts a new generation, and a sending thread stops once its generation is over
+    generation: u64,
+    // Distance that hasn't been sent yet
+    remaining: (f32, f32),
+}
+
+// Turns one huge delta into a coasting scroll. The distance goes out a little every frame along
+// the curve, so the scroll slows down and ends up exactly as far once the duration is over.
+// Each axis coasts with its own distance and duration, and stops on its own.
+pub struct Fling {
+    state: Arc<Mutex<FlingState>>,
+    curve: FlingCurve,
+    // Per axis
+    duration: (Duration, Duration),
+    emitter: Emitter,
+}
+
+// What an axis still has to go after `elapsed`, along the curve from `total`
+fn remaining_distance(curve: FlingCurve, total: f32, elapsed: Duration, duration: Duration) -> f32 {
+    let t = if duration.is_zero() {
+        1.0
+    } else {
+        elapsed.as_secs_f32() / duration.as_secs_f32()
+    };
+    let remaining = total * (1.0 - curve.progress(t));
+    if t >= 1.0 || remaining.abs() <= STOP_DISTANCE {
+        0.0
+    } else {
+        remaining
+    }
+}
+
+impl Fling {
+    pub fn new(curve: FlingCurve, duration: (Duration, Duration), emitter: Emitter) -> Self {
+        Fling {
+            state: Arc::new(Mutex::new(FlingState::default())),
+            curve,
+            duration,
+            emitter,
+        }
+    }
+
+    // Flicking again in the same direction adds to what is left, and starts the curve over
+    pub fn start(&self, delta_x: f32, delta_y: f32) {
+        let mut state = self.state.lock().unwrap();
+        let (remaining_x, remaining_y) = state.remaining;
+        let total = if same_direction(remaining_x, delta_x) && same_direction(remaining_y, delta_y)
+        {
+            (remaining_x + delta_x, remaining_y + delta_y)
+        } else {
+            (delta_x, delta_y)
+        };
+        state.remaining = total;
+        state.generation += 1;
+        let generation = state.generation;
+        info!("Flinging ({}, {}) lines", total.0, total.1);
+        drop(state);
+
+        let state = self.state.clone();
+        let emitter = self.emitter.clone();
+        let (curve, duration) = (self.curve, self.duration);
+        let started = Instant::now();
+        thread::spawn(move || loop {
+            thread::sleep(TICK);
+            let elapsed = started.elapsed();
+            let mut state = state.lock().unwrap();
+            if state.generation != generation {
+                return;
+            }
+            // What is left should shrink to what the curve still has to go
+            let (remaining_x, remaining_y) = state.remaining;
+            state.remaining = (
+                remaining_distance(curve, total.0, elapsed, duration.0),
+                remaining_distance(curve, total.1, elapsed, duration.1),
+            );
+            let step = (
+                remaining_x - state.remaining.0,
+                remaining_y - state.remaining.1,
+            );
+            let done = state.remaining == (0.0, 0.0);
+            drop(state);
+            emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+                step.0, step.1,
+            )));
+            if done {
+                return;
+            }
+        });
+    }
+
+    // Scrolling against the fling stops it, scrolling along with it lets it coast on
+    pub fn interrupt(&self, delta_x: f32, delta_y: f32) {
+        let state = self.state.lock().unwrap();
+        let (remaining_x, remaining_y) = state.remaining;
+        let reversed =
+            !same_direction(remaining_x, delta_x) || !same_direction(remaining_y, delta_y);
+        drop(state);
+        if reversed {
+            self.stop();
+        }
+    }
+
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.remaining != (0.0, 0.0) {
+            info!("Stopped the fling");
+        }
+        state.generation += 1;
+        state.remaining = (0.0, 0.0);
+    }
+}
+
+fn same_direction(a: f32, b: f32) -> bool {
+    a == 0.0 || b == 0.0 || a.signum() == b.signum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagonal_fling_axes_decay_proportionally() {
+        let curve = FlingCurve::Exponential;
+        let duration = Duration::from_millis(500);
+        for millis in [16, 100, 250, 400] {
+            let elapsed = Duration::from_millis(millis);
+            let remaining_x = remaining_distance(curve, 4.0, elapsed, duration);
+            let remaining_y = remaining_distance(curve, 20.0, elapsed, duration);
+            assert!(remaining_x > 0.0);
+            assert!((remaining_y / remaining_x - 5.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn diagonal_fling_axes_stop_separately() {
+        let curve = FlingCurve::Linear;
+        let duration = (Duration::from_millis(200), Duration::from_millis(400));
+        let remaining = |millis| {
+            let elapsed = Duration::from_millis(millis);
+            (
+                remaining_distance(curve, 4.0, elapsed, duration.0),
+                remaining_distance(curve, 20.0, elapsed, duration.1),
+            )
+        };
+
+        let (remaining_x, remaining_y) = remaining(100);
+        assert!(remaining_x > 0.0 && remaining_y > 0.0);
+        // The horizontal axis is done, while the vertical one still coasts
+        let (remaining_x, remaining_y) = remaining(300);
+        assert_eq!(remaining_x, 0.0);
+        assert!(remaining_y > 0.0);
+        assert_eq!(remaining(400), (0.0, 0.0));
+    }
+
+    // Share of the distance per share of the duration, from `t` onwards
+    fn speed(curve: FlingCurve, t: f32) -> f32 {
+        let step = 0.001;
+        (curve.progress(t + step) - curve.progress(t)) / step
+    }
+
+    #[test]
+    fn every_curve_comes_to_a_stop_within_the_duration() {
+        let duration = Duration::from_millis(1500);
+        for curve in [
+            FlingCurve::Exponential,
+            FlingCurve::Linear,
+            FlingCurve::EaseOut,
+        ] {
+            let start_speed = speed(curve, 0.0);
+            let mut last_speed = start_speed;
+            for step in 1..1000 {
+                let speed = speed(curve, step as f32 / 1000.0);
+                assert!(speed >= 0.0, "{:?} turns around", curve);
+                assert!(speed <= last_speed + 1e-3, "{:?} speeds up", curve);
+                last_speed = speed;
+            }
+            assert!(
+                last_speed < start_speed * 0.01,
+                "{:?} still has {} of its start speed {}",
+                curve,
+                last_speed,
+                start_speed
+            );
+            assert_eq!(remaining_distance(curve, 10.0, duration, duration), 0.0);
+        }
+    }
+
+    #[test]
+    fn ease_out_keeps_its_speed_for_longer() {
+        assert!(speed(FlingCurve::EaseOut, 0.0) < speed(FlingCurve::Linear, 0.0));
+        assert!(speed(FlingCurve::EaseOut, 0.8) > speed(FlingCurve::Linear, 0.8));
+        assert!(speed(FlingCurve::EaseOut, 0.8) > speed(FlingCurve::Exponential, 0.8));
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rdev::{EventType, MouseScrollDelta};
+
+use crate::deadline::DeadlineTimer;
+use crate::emitter::Emitter;
+use crate::filter::SharedFilter;
+use crate::units::Lines;
+
+// What was dropped since the last kept event
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DroppedDeltas {
+    pub delta: (f32, f32),
+    // Dropped events since the last change of direction
+    pub events: u32,
+    pub last_delta: (f32, f32),
+}
+
+impl DroppedDeltas {
+    // Counts a dropped event whose distance is kept elsewhere, like in a conserving filter
+    pub fn count(&mut self, delta_x: f32, delta_y: f32) {
+        let reversed = delta_x * self.last_delta.0 < 0.0 || delta_y * self.last_delta.1 < 0.0;
+        self.events = if reversed { 1 } else { self.events + 1 };
+        self.last_delta = (delta_x, delta_y);
+    }
+
+    // Counts a dropped event and adds up its distance, capped to `max` on each axis
+    pub fn add(&mut self, delta_x: f32, delta_y: f32, max: (Lines, Lines)) {
+        self.count(delta_x, delta_y);
+        let (max_x, max_y) = max;
+        self.delta = (
+            (self.delta.0 + delta_x).clamp(-max_x.0, max_x.0),
+            (self.delta.1 + delta_y).clamp(-max_y.0, max_y.0),
+        );
+    }
+}
+
+// Sends the distance that was dropped while scrolling too slowly, once the gesture has ended.
+// Otherwise a slow partial scroll is lost entirely when the wheel stops.
+pub struct DroppedFlusher {
+    timer: DeadlineTimer,
+    delay: Duration,
+}
+
+impl DroppedFlusher {
+    pub fn new(
+        delay: Duration,
+        min_distance: Lines,
+        min_events: u32,
+        dropped_deltas: Arc<Mutex<DroppedDeltas>>,
+        filter: SharedFilter,
+        emitter: Emitter,
+    ) -> Self {
+        let timer = DeadlineTimer::new(move || {
+            // A conserving filter still holds the rest of the gesture
+            let backlog = filter.lock().unwrap().take_backlog();
+            let mut dropped_deltas = dropped_deltas.lock().unwrap();
+            let delta_x = dropped_deltas.delta.0 + backlog.0;
+            let delta_y = dropped_deltas.delta.1 + backlog.1;
+            let magnitude = Lines(delta_x.abs().max(delta_y.abs()));
+            if magnitude.0 > 0.0 && magnitude >= min_distance && dropped_deltas.events >= min_events
+            {
+                *dropped_deltas = Default::default();
+                emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+                    delta_x, delta_y,
+                )));
+            } else {
+                // Anything below the minimum keeps lingering until the next gesture, like before.
+                // The backlog goes back into the filter, where the next kept event or an idle
+                // reset sends it, instead of being cleared along with the dropped distance.
+                drop(dropped_deltas);
+                filter.lock().unwrap().hold_back(backlog.0, backlog.1);
+            }
+            None
+        });
+        DroppedFlusher { timer, delay }
+    }
+
+    // Called for every dropped event, the gesture ends once this hasn't been called for the delay
+    pub fn schedule(&self) {
+        self.timer.set(Instant::now() + self.delay);
+    }
+}
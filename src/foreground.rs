@@ -0,0 +1,133 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Focus changes don't have to be noticed right away, it only matters by the next scroll
+const FOCUS_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::Foundation::{CloseHandle, HWND};
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetClassNameW, GetForegroundWindow, GetWindowThreadProcessId,
+    };
+
+    pub type WindowId = HWND;
+
+    pub fn foreground_window() -> WindowId {
+        unsafe { GetForegroundWindow() }
+    }
+
+    pub fn executable_name(window: WindowId) -> Option<String> {
+        let mut process_id = 0;
+        unsafe { GetWindowThreadProcessId(window, &mut process_id) };
+        if process_id == 0 {
+            return None;
+        }
+
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id) };
+        if process == 0 {
+            return None;
+        }
+        let mut buffer = [0u16; 1024];
+        let mut size = buffer.len() as u32;
+        let success = unsafe {
+            QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buffer.as_mut_ptr(), &mut size)
+        };
+        unsafe { CloseHandle(process) };
+        if success == 0 {
+            return None;
+        }
+
+        let path = String::from_utf16_lossy(&buffer[..size as usize]);
+        path.rsplit('\\').next().map(|name| name.to_string())
+    }
+
+    pub fn class_name(window: WindowId) -> Option<String> {
+        // Class names are at most 256 characters long
+        let mut buffer = [0u16; 256];
+        let length = unsafe { GetClassNameW(window, buffer.as_mut_ptr(), buffer.len() as i32) };
+        if length <= 0 {
+            return None;
+        }
+        Some(String::from_utf16_lossy(&buffer[..length as usize]))
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub type WindowId = usize;
+
+    pub fn foreground_window() -> WindowId {
+        0
+    }
+
+    pub fn executable_name(_window: WindowId) -> Option<String> {
+        None
+    }
+
+    pub fn class_name(_window: WindowId) -> Option<String> {
+        None
+    }
+}
+
+// Looks up which app has focus. The process is only queried again when the focused window changes.
+#[derive(Default)]
+pub struct ForegroundApp {
+    cached: Mutex<Option<(platform::WindowId, Option<String>)>>,
+    cached_class: Mutex<Option<(platform::WindowId, Option<String>)>>,
+    // When focus was last checked, and which window had it
+    last_focus: Mutex<Option<(Instant, platform::WindowId)>>,
+}
+
+impl ForegroundApp {
+    // Executable file name of the focused window, like "firefox.exe"
+    pub fn executable(&self) -> Option<String> {
+        let window = platform::foreground_window();
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((cached_window, executable)) = &*cached {
+            if *cached_window == window {
+                return executable.clone();
+            }
+        }
+        let executable = platform::executable_name(window);
+        *cached = Some((window, executable.clone()));
+        executable
+    }
+
+    // Window class of the focused window, like "Chrome_WidgetWin_1". Unlike the executable, it
+    // tells apart different kinds of windows of the same process.
+    pub fn class_name(&self) -> Option<String> {
+        let window = platform::foreground_window();
+        let mut cached_class = self.cached_class.lock().unwrap();
+        if let Some((cached_window, class_name)) = &*cached_class {
+            if *cached_window == window {
+                return class_name.clone();
+            }
+        }
+        let class_name = platform::class_name(window);
+        *cached_class = Some((window, class_name.clone()));
+        class_name
+    }
+
+    // Whether another window has focus than at the last check. Within FOCUS_CHECK_INTERVAL of
+    // the last check, this returns false without asking the OS again.
+    pub fn focus_changed(&self) -> bool {
+        let now = Instant::now();
+        let mut last_focus = self.last_focus.lock().unwrap();
+        if let Some((checked, _)) = *last_focus {
+            if now.duration_since(checked) < FOCUS_CHECK_INTERVAL {
+                return false;
+            }
+        }
+        let window = platform::foreground_window();
+        let changed = matches!(*last_focus, Some((_, last_window)) if last_window != window);
+        *last_focus = Some((now, window));
+        changed
+    }
+}
@@ -0,0 +1,116 @@
+// Output multiplier for a scroll speed, from a piecewise linear curve of `(speed, multiplier)` points.
+// The points are sorted by speed. Outside of the curve, the nearest endpoint is used,
+// and an empty curve leaves the output unchanged.
+pub fn gain(points: &[(f32, f32)], speed: f32) -> f32 {
+    let (Some(&(first_speed, first_gain)), Some(&(last_speed, last_gain))) =
+        (points.first(), points.last())
+    else {
+        return 1.0;
+    };
+    if speed.is_nan() || speed <= first_speed {
+        return first_gain;
+    }
+    if speed >= last_speed {
+        return last_gain;
+    }
+
+    for pair in points.windows(2) {
+        let ((start_speed, start_gain), (end_speed, end_gain)) = (pair[0], pair[1]);
+        // Earlier pairs ended below the speed, so it is above start_speed here
+        if speed <= end_speed {
+            let t = (speed - start_speed) / (end_speed - start_speed);
+            return start_gain + t * (end_gain - start_gain);
+        }
+    }
+    last_gain
+}
+
+// Picks a gear from `(speed, multiplier)` gears sorted by speed, where None means below the first one.
+// A higher gear is only engaged once the speed is `hysteresis` (as a fraction) above its threshold,
+// and the current gear is only left once the speed is that far below it, so that a speed right
+// at a threshold doesn't keep shifting back and forth.
+pub fn select_gear(
+    gears: &[(f32, f32)],
+    current: Option<usize>,
+    speed: f32,
+    hysteresis: f32,
+) -> Option<usize> {
+    // Events without time in between have no meaningful speed to shift by
+    if !speed.is_finite() {
+        return current;
+    }
+    let upshift = gears
+        .iter()
+        .rposition(|&(threshold, _)| speed >= threshold * (1.0 + hysteresis));
+    if upshift > current {
+        return upshift;
+    }
+    let mut gear = current;
+    while let Some(index) = gear {
+        if speed >= gears[index].0 * (1.0 - hysteresis) {
+            break;
+        }
+        gear = index.checked_sub(1);
+    }
+    gear
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Engaged at 1.25 and 2.5, left below 0.75 and 1.5
+    const GEARS: [(f32, f32); 2] = [(1.0, 2.0), (2.0, 4.0)];
+    const HYSTERESIS: f32 = 0.25;
+    const JUST: f32 = 1e-4;
+
+    fn shift(current: Option<usize>, speed: f32) -> Option<usize> {
+        select_gear(&GEARS, current, speed, HYSTERESIS)
+    }
+
+    #[test]
+    fn upshifts_at_the_threshold_plus_the_hysteresis() {
+        assert_eq!(shift(None, 1.25), Some(0));
+        assert_eq!(shift(None, 1.25 - JUST), None);
+        assert_eq!(shift(Some(0), 2.5), Some(1));
+        assert_eq!(shift(Some(0), 2.5 - JUST), Some(0));
+    }
+
+    #[test]
+    fn downshifts_at_the_threshold_minus_the_hysteresis() {
+        assert_eq!(shift(Some(1), 1.5), Some(1));
+        assert_eq!(shift(Some(1), 1.5 - JUST), Some(0));
+        assert_eq!(shift(Some(0), 0.75), Some(0));
+        assert_eq!(shift(Some(0), 0.75 - JUST), None);
+    }
+
+    #[test]
+    fn a_speed_at_a_threshold_keeps_its_gear() {
+        for threshold in [1.0, 2.0] {
+            for start in [None, Some(0), Some(1)] {
+                let first = shift(start, threshold);
+                let mut gear = first;
+                for _ in 0..10 {
+                    gear = shift(gear, threshold);
+                    assert_eq!(gear, first, "shifted at {} from {:?}", threshold, start);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shifts_up_from_below_the_first_gear() {
+        assert_eq!(shift(None, 2.0), Some(0));
+        // Fast enough for the second gear right away
+        assert_eq!(shift(None, 10.0), Some(1));
+    }
+
+    #[test]
+    fn keeps_the_gear_without_a_finite_speed() {
+        for current in [None, Some(0), Some(1)] {
+            assert_eq!(shift(current, f32::NAN), current);
+            assert_eq!(shift(current, f32::INFINITY), current);
+            assert_eq!(shift(current, f32::NEG_INFINITY), current);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rdev::{simulate, EventType, MouseScrollDelta};
+use tracing::{error, info, warn};
+
+use crate::event_log::{read_event_log, write_event_log, EventLogFormat, ScriptLine};
+
+// Records the wheel input to a file, and replays it through the OS later.
+// Replayed events come back through the grab callback and get smoothed like real ones,
+// which makes it easy to compare settings on the exact same gesture.
+//
+// Recordings can be passed to `--script` as well.
+pub struct GestureRecorder {
+    path: PathBuf,
+    format: EventLogFormat,
+    // Raw wheel events since recording started, or None while not recording
+    recording: Mutex<Option<Vec<(SystemTime, f32, f32)>>>,
+    replaying: Arc<AtomicBool>,
+}
+
+impl GestureRecorder {
+    pub fn new(path: PathBuf, format: EventLogFormat) -> Self {
+        GestureRecorder {
+            path,
+            format,
+            recording: Mutex::new(None),
+            replaying: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn toggle_recording(&self) {
+        let mut recording = self.recording.lock().unwrap();
+        match recording.take() {
+            None => {
+                info!("Recording scroll gesture");
+                *recording = Some(Vec::new());
+            }
+            Some(events) => {
+                match write_event_log(&self.path, &script_lines(&events), self.format) {
+                    Ok(()) => info!(
+                        "Saved scroll gesture with {} events to {:?}",
+                        events.len(),
+                        self.path
+                    ),
+                    Err(error) => error!("Failed to save scroll gesture: {:?}", error),
+                }
+            }
+        }
+    }
+
+    pub fn record(&self, time: SystemTime, delta_x: f32, delta_y: f32) {
+        if let Some(events) = self.recording.lock().unwrap().as_mut() {
+            events.push((time, delta_x, delta_y));
+        }
+    }
+
+    pub fn replay(&self) {
+        if self.replaying.swap(true, Ordering::Relaxed) {
+            warn!("Already replaying a scroll gesture");
+            return;
+        }
+        let path = self.path.clone();
+        let replaying = self.replaying.clone();
+        thread::spawn(move || {
+            if let Err(error) = replay_file(&path) {
+                error!("Failed to replay scroll gesture: {:?}", error);
+            }
+            replaying.store(false, Ordering::Relaxed);
+        });
+    }
+}
+
+fn replay_file(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let script = read_event_log(path)?;
+    info!("Replaying scroll gesture from {:?}", path);
+
+    let start_time = Instant::now();
+    for (millis, delta_x, delta_y) in script {
+        let due = start_time + Duration::from_millis(millis);
+        thread::sleep(due.saturating_duration_since(Instant::now()));
+        simulate(&EventType::Wheel(MouseScrollDelta::LineDelta(
+            delta_x, delta_y,
+        )))
+        .map_err(|error| format!("Failed to simulate scroll: {:?}", error))?;
+    }
+    Ok(())
+}
+
+fn script_lines(events: &[(SystemTime, f32, f32)]) -> Vec<ScriptLine> {
+    let Some((start_time, _, _)) = events.first() else {
+        return Vec::new();
+    };
+    events
+        .iter()
+        .map(|(time, delta_x, delta_y)| {
+            let millis = time
+                .duration_since(*start_time)
+                .unwrap_or_default()
+                .as_millis();
+            (millis as u64, *delta_x, *delta_y)
+        })
+        .collect()
+}
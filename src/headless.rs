@@ -0,0 +1,114 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use rdev::{Event, EventType, MouseScrollDelta};
+
+use crate::event_handler::{EventHandler, EventHandlerConfig};
+use crate::event_log::{read_event_log, ScriptLine};
+
+// Feeds a scripted stream of wheel events through the handler instead of grabbing the mouse,
+// so that config loading, smoothing and emission can be checked without an input device.
+//
+// Every line of the script is `<milliseconds since start> <delta_x> <delta_y>`,
+// empty lines and lines starting with `#` are skipped. Binary event logs work as well.
+pub fn run(
+    handler: &EventHandler,
+    emitted: &Arc<Mutex<Vec<EventType>>>,
+    script_path: &Path,
+) -> Result<Vec<EventType>, Box<dyn std::error::Error>> {
+    let script = read_event_log(script_path)?;
+    let start_time = SystemTime::now();
+
+    let mut output = Vec::new();
+    for (millis, delta_x, delta_y) in script {
+        let event = Event {
+            time: start_time + Duration::from_millis(millis),
+            name: None,
+            event_type: EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)),
+        };
+        if let Some(event) = handler.callback(event) {
+            output.push(event.event_type);
+        }
+        output.append(&mut emitted.lock().unwrap());
+    }
+    Ok(output)
+}
+
+// These send scroll events from their own threads, on the real clock instead of the script's.
+// Their output would come after the script is done and be missing, so they are refused instead.
+pub fn check_config(config: &EventHandlerConfig) -> Result<(), String> {
+    let background_settings: Vec<&str> = [
+        ("burst_merge_ms", !config.burst_merge_window.is_zero()),
+        ("timer_output_hz", !config.timer_output_interval.is_zero()),
+        ("fling_threshold", config.fling_threshold.0 > 0.0),
+        (
+            "flush_dropped_after_ms",
+            !config.flush_dropped_after.is_zero(),
+        ),
+        ("emit_scroll_end", config.emit_scroll_end),
+        (
+            "max_lines_per_second_x and max_lines_per_second_y",
+            config.max_lines_per_second != (0.0, 0.0),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(setting, used)| used.then_some(setting))
+    .collect();
+    if background_settings.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "--script can't show what {} send later on, turn them off for scripted runs",
+        background_settings.join(", ")
+    ))
+}
+
+pub fn parse_script(script: &str) -> Result<Vec<ScriptLine>, String> {
+    let mut lines = Vec::new();
+    for (line_number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = parse_line(line)
+            .ok_or_else(|| format!("Invalid script line {}: {:?}", line_number + 1, line))?;
+        lines.push(parsed);
+    }
+    Ok(lines)
+}
+
+fn parse_line(line: &str) -> Option<ScriptLine> {
+    let mut parts = line.split_whitespace();
+    let millis = parts.next()?.parse().ok()?;
+    let delta_x = parts.next()?.parse().ok()?;
+    let delta_y = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((millis, delta_x, delta_y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_config::AppConfig;
+
+    #[test]
+    fn refuses_settings_that_send_events_later_on() {
+        assert!(check_config(&EventHandlerConfig::from(&AppConfig::default())).is_ok());
+        let config = AppConfig {
+            burst_merge_ms: 10,
+            emit_scroll_end: true,
+            ..AppConfig::default()
+        };
+        let error = check_config(&EventHandlerConfig::from(&config)).unwrap_err();
+        assert!(
+            error.contains("burst_merge_ms, emit_scroll_end"),
+            "{}",
+            error
+        );
+    }
+}
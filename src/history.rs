@@ -0,0 +1,46 @@
+use std::{collections::VecDeque, sync::Mutex, time::SystemTime};
+
+use crate::event_handler::ScrollDecision;
+
+// One scroll event that went through the smoothing and the keep or drop decision
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryEntry {
+    pub raw: (f32, f32),
+    // Before the gain curve
+    pub smoothed: (f32, f32),
+    pub alpha: f32,
+    pub decision: ScrollDecision,
+    pub timestamp: SystemTime,
+}
+
+// The last few scroll events, for anything that wants to look back at recent scrolling.
+// The oldest entry makes room once it is full. A capacity of zero records nothing.
+pub struct ScrollHistory {
+    capacity: usize,
+    entries: Mutex<VecDeque<HistoryEntry>>,
+}
+
+impl ScrollHistory {
+    pub fn new(capacity: usize) -> Self {
+        ScrollHistory {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn record(&self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    // Oldest first. The lock is only held to copy the entries.
+    pub fn snapshot(&self) -> Vec<HistoryEntry> {
+        self.entries.lock().unwrap().iter().copied().collect()
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use rdev::{Button, EventType, Key};
+
+// Only keys that are sensible as a global toggle are supported.
+pub fn parse_key(name: &str) -> Option<Key> {
+    let key = match name {
+        "ScrollLock" => Key::ScrollLock,
+        "Pause" => Key::Pause,
+        "PrintScreen" => Key::PrintScreen,
+        "Insert" => Key::Insert,
+        "F1" => Key::F1,
+        "F2" => Key::F2,
+        "F3" => Key::F3,
+        "F4" => Key::F4,
+        "F5" => Key::F5,
+        "F6" => Key::F6,
+        "F7" => Key::F7,
+        "F8" => Key::F8,
+        "F9" => Key::F9,
+        "F10" => Key::F10,
+        "F11" => Key::F11,
+        "F12" => Key::F12,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Meta,
+}
+
+impl Modifier {
+    // Left and right variant
+    pub fn keys(self) -> [Key; 2] {
+        match self {
+            Modifier::Ctrl => [Key::ControlLeft, Key::ControlRight],
+            Modifier::Shift => [Key::ShiftLeft, Key::ShiftRight],
+            Modifier::Alt => [Key::Alt, Key::AltGr],
+            Modifier::Meta => [Key::MetaLeft, Key::MetaRight],
+        }
+    }
+}
+
+pub fn parse_modifier(name: &str) -> Option<Modifier> {
+    let modifier = match name {
+        "Ctrl" => Modifier::Ctrl,
+        "Shift" => Modifier::Shift,
+        "Alt" => Modifier::Alt,
+        "Meta" => Modifier::Meta,
+        _ => return None,
+    };
+    Some(modifier)
+}
+
+// Keys and the primary mouse button that are currently held down, as seen by the grab callback
+#[derive(Debug, Default)]
+pub struct HeldKeys {
+    keys: Mutex<Vec<Key>>,
+    primary_button: AtomicBool,
+}
+
+impl HeldKeys {
+    pub fn update(&self, event_type: &EventType) {
+        let mut keys = self.keys.lock().unwrap();
+        match *event_type {
+            EventType::KeyPress(key) if !keys.contains(&key) => keys.push(key),
+            EventType::KeyRelease(key) => keys.retain(|held| *held != key),
+            EventType::ButtonPress(Button::Left) => {
+                self.primary_button.store(true, Ordering::Relaxed)
+            }
+            EventType::ButtonRelease(Button::Left) => {
+                self.primary_button.store(false, Ordering::Relaxed)
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_held(&self, key: Key) -> bool {
+        self.keys.lock().unwrap().contains(&key)
+    }
+
+    pub fn is_primary_button_held(&self) -> bool {
+        self.primary_button.load(Ordering::Relaxed)
+    }
+
+    pub fn is_modifier_held(&self, modifier: Modifier) -> bool {
+        let keys = self.keys.lock().unwrap();
+        modifier.keys().iter().any(|key| keys.contains(key))
+    }
+}
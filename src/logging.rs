@@ -0,0 +1,257 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn, Level, Metadata};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, fmt::MakeWriter, layer::SubscriberExt, reload, Layer,
+};
+
+use crate::windows_event_log::{self, EventSource, Severity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+// The log file is only opened once the config asks for it, until then its output goes nowhere
+#[derive(Clone, Default)]
+struct FileWriter(Arc<Mutex<Option<NonBlocking>>>);
+
+impl<'a> MakeWriter<'a> for FileWriter {
+    type Writer = Box<dyn Write + 'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        match self.0.lock().unwrap().clone() {
+            Some(writer) => Box::new(writer),
+            None => Box::new(io::sink()),
+        }
+    }
+}
+
+// Reports every log event as one entry of the Windows Event Log, once the source is open
+#[derive(Clone, Default)]
+struct EventLogWriter(Arc<Mutex<Option<Arc<EventSource>>>>);
+
+// Collects the formatted event, and reports it when the log layer is done writing
+struct EventLogEntry {
+    source: Arc<EventSource>,
+    severity: Severity,
+    message: Vec<u8>,
+}
+
+impl Write for EventLogEntry {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.message.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventLogEntry {
+    fn drop(&mut self) {
+        let message = String::from_utf8_lossy(&self.message);
+        self.source.report(self.severity, message.trim_end());
+    }
+}
+
+impl EventLogWriter {
+    fn entry(&self, severity: Severity) -> Box<dyn Write> {
+        match self.0.lock().unwrap().clone() {
+            Some(source) => Box::new(EventLogEntry {
+                source,
+                severity,
+                message: Vec::new(),
+            }),
+            None => Box::new(io::sink()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for EventLogWriter {
+    type Writer = Box<dyn Write + 'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.entry(Severity::Information)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let severity = if *meta.level() == Level::ERROR {
+            Severity::Error
+        } else if *meta.level() == Level::WARN {
+            Severity::Warning
+        } else {
+            Severity::Information
+        };
+        self.entry(severity)
+    }
+}
+
+type SetLevel = Box<dyn Fn(LevelFilter) -> Result<(), reload::Error> + Send + Sync>;
+
+// Logs to the console and optionally to the `logs` folder. Both levels sit behind `tracing`'s
+// reload layer, so that they can be changed at runtime, for example by the active profile.
+// Warnings and errors can also go to the Windows Event Log, which profiles and boosts don't touch.
+pub struct Logging {
+    console_level: SetLevel,
+    file_level: SetLevel,
+    file_writer: FileWriter,
+    event_log_level: SetLevel,
+    event_log_writer: EventLogWriter,
+    // Flushes the log file when dropped
+    file_guard: Mutex<Option<WorkerGuard>>,
+    // Whether to log to the file and the level, as last applied
+    settings: Mutex<(bool, LogLevel)>,
+    // End of the current temporary trace logging
+    boosted_until: Mutex<Option<Instant>>,
+}
+
+impl Logging {
+    // Starts with info logs on the console, before the config has been read
+    pub fn initialize() -> Result<Self, Box<dyn std::error::Error>> {
+        let (console_filter, console_handle) = reload::Layer::new(LevelFilter::INFO);
+        let (file_filter, file_handle) = reload::Layer::new(LevelFilter::OFF);
+        let file_writer = FileWriter::default();
+        let (event_log_filter, event_log_handle) = reload::Layer::new(LevelFilter::OFF);
+        let event_log_writer = EventLogWriter::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(fmt::layer().with_filter(console_filter))
+            .with(
+                fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(file_writer.clone())
+                    .with_filter(file_filter),
+            )
+            .with(
+                // The Event Log adds its own timestamp
+                fmt::layer()
+                    .with_ansi(false)
+                    .without_time()
+                    .with_writer(event_log_writer.clone())
+                    .with_filter(event_log_filter),
+            );
+        tracing::subscriber::set_global_default(subscriber)?;
+
+        Ok(Logging {
+            console_level: Box::new(move |level| console_handle.reload(level)),
+            file_level: Box::new(move |level| file_handle.reload(level)),
+            file_writer,
+            event_log_level: Box::new(move |level| event_log_handle.reload(level)),
+            event_log_writer,
+            file_guard: Mutex::new(None),
+            settings: Mutex::new((false, LogLevel::Info)),
+            boosted_until: Mutex::new(None),
+        })
+    }
+
+    // Opens the log file. Logging to it only starts once `apply` says so.
+    pub fn open_file(&self) {
+        let mut file_guard = self.file_guard.lock().unwrap();
+        if file_guard.is_some() {
+            return;
+        }
+        let file_appender = tracing_appender::rolling::daily("logs", "app.log");
+        let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+        *self.file_writer.0.lock().unwrap() = Some(non_blocking);
+        *file_guard = Some(guard);
+    }
+
+    // Starts reporting warnings and errors to the Windows Event Log
+    pub fn open_event_log(&self) {
+        if self.event_log_writer.0.lock().unwrap().is_some() {
+            return;
+        }
+        // Without the registration the entries are still logged, but the Event Viewer complains
+        // that it can't find their description
+        if let Err(error) = windows_event_log::install() {
+            warn!(
+                "Could not register the Event Log source, run the app as administrator once: {}",
+                error
+            );
+        }
+        match EventSource::open() {
+            Ok(source) => {
+                *self.event_log_writer.0.lock().unwrap() = Some(Arc::new(source));
+                match (self.event_log_level)(LevelFilter::WARN) {
+                    Ok(()) => info!("Logging warnings and errors to the Windows Event Log"),
+                    Err(error) => error!("Failed to change the log level: {}", error),
+                }
+            }
+            Err(error) => warn!("Could not open the Windows Event Log: {}", error),
+        }
+    }
+
+    // While boosted, the settings only take effect once the boost is over
+    pub fn apply(&self, log_to_file: bool, level: LogLevel) {
+        *self.settings.lock().unwrap() = (log_to_file, level);
+        if self.boosted_until.lock().unwrap().is_none() {
+            self.set_levels(log_to_file, level);
+        }
+    }
+
+    // Logs everything for a while, to capture a rare glitch without leaving verbose logging on.
+    // Boosting again while boosted starts the duration over.
+    pub fn boost(self: &Arc<Self>, duration: Duration) {
+        let until = Instant::now() + duration;
+        *self.boosted_until.lock().unwrap() = Some(until);
+        let (log_to_file, _) = *self.settings.lock().unwrap();
+        self.set_levels(log_to_file, LogLevel::Trace);
+        info!("Logging everything for {:?}", duration);
+
+        let logging = self.clone();
+        thread::spawn(move || {
+            thread::sleep(duration);
+            let mut boosted_until = logging.boosted_until.lock().unwrap();
+            if *boosted_until != Some(until) {
+                return;
+            }
+            *boosted_until = None;
+            drop(boosted_until);
+            let (log_to_file, level) = *logging.settings.lock().unwrap();
+            logging.set_levels(log_to_file, level);
+        });
+    }
+
+    fn set_levels(&self, log_to_file: bool, level: LogLevel) {
+        let file_level = if log_to_file && self.file_guard.lock().unwrap().is_some() {
+            level.into()
+        } else {
+            LevelFilter::OFF
+        };
+        let result =
+            (self.console_level)(level.into()).and_then(|()| (self.file_level)(file_level));
+        match result {
+            Ok(()) => info!(
+                "Logging at {:?}, to file: {}",
+                level,
+                file_level != LevelFilter::OFF
+            ),
+            Err(error) => error!("Failed to change the log level: {}", error),
+        }
+    }
+}
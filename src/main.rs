@@ -1,162 +1,388 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod announce;
 mod app_config;
+mod app_state;
+mod burst;
+mod calibrate;
+mod cli;
+mod clock;
+mod control;
+mod deadline;
+mod diagnose;
+mod display;
+mod emitter;
+mod event_handler;
+mod event_log;
+mod filter;
+mod fling;
+mod flush;
+mod foreground;
+mod gain;
+mod gesture;
+mod headless;
+mod history;
+mod hotkey;
+mod logging;
+mod notify;
+mod power;
+mod profile;
+mod rate_limit;
+mod remote_session;
+mod reversal_toggle;
+mod scroll_end;
+mod timer_output;
+mod trace;
+mod units;
+mod velocity_lock;
+mod window_message;
+mod windows_event_log;
 
-use rdev::{grab, Event, EventType, EventTypes, MouseScrollDelta};
+use rdev::{grab, Event, EventTypes};
 use std::{
-    sync::{Arc, Mutex},
-    time::{self},
+    backtrace::Backtrace,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
 };
-use tracing::{error, info};
-use tracing_subscriber::FmtSubscriber;
+use tracing::{error, info, warn};
 
-use crate::app_config::read_config;
+use crate::announce::CommandAnnouncer;
+use crate::app_config::{init_config, read_config_or_default, resolve_config_path};
+use crate::app_state::StateStore;
+use crate::cli::parse_args;
+use crate::clock::SystemClock;
+use crate::control::{spawn_control_server, ControlState};
+use crate::emitter::{Emitter, OutputBackend};
+use crate::event_handler::{
+    EventHandler, EventHandlerConfig, ScrollCounters, ScrollDecision, ScrollDiagnostics,
+};
+use crate::history::{HistoryEntry, ScrollHistory};
+use crate::logging::Logging;
+use crate::notify::notify;
+use crate::remote_session::{is_remote_session, RemoteSessionPolicy};
+use crate::trace::WheelTrace;
 
-fn initialize_logging() -> Result<(), Box<dyn std::error::Error>> {
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(tracing::Level::INFO)
-        .finish();
+// Without a console window, a panic would otherwise end the app without leaving a trace
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture();
+        error!("{}\nBacktrace:\n{}", info, backtrace);
+        default_hook(info);
+    }));
+}
 
-    tracing::subscriber::set_global_default(subscriber)?;
+// Grabbing can succeed on locked-down systems without ever delivering scroll events
+fn spawn_wheel_watchdog(wheel_event_seen: Arc<AtomicBool>, window: Duration) {
+    thread::spawn(move || {
+        thread::sleep(window);
+        if !wheel_event_seen.load(Ordering::Relaxed) {
+            warn!(
+                "No scroll events received within {:?} of starting. \
+                If you have been scrolling, the app might lack the permissions to intercept \
+                mouse input, or the mouse driver is bypassing it.",
+                window
+            );
+        }
+    });
+}
 
-    Ok(())
+// Confirms that a long running session is still alive, and what it is doing
+fn spawn_heartbeat(
+    counters: Arc<ScrollCounters>,
+    diagnostics: Arc<ScrollDiagnostics>,
+    history: Arc<ScrollHistory>,
+    enabled: Arc<AtomicBool>,
+    active_profile: Arc<Mutex<Option<String>>>,
+    config_path: PathBuf,
+    interval: Duration,
+) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let processed = counters.processed.swap(0, Ordering::Relaxed);
+        let dropped = counters.dropped.swap(0, Ordering::Relaxed);
+        let drop_rate = if processed > 0 {
+            dropped as f32 / processed as f32 * 100.0
+        } else {
+            0.0
+        };
+        info!(
+            "Heartbeat: config {:?}, profile {:?}, enabled {}, {} scroll events in the last {:?}, {:.1}% dropped",
+            config_path,
+            active_profile.lock().unwrap(),
+            enabled.load(Ordering::Relaxed),
+            processed,
+            interval,
+            drop_rate
+        );
+        info!(
+            "Last scroll: raw ({}, {}), smoothed ({}, {}), alpha {}",
+            diagnostics.raw_x(),
+            diagnostics.raw_y(),
+            diagnostics.smoothed_x(),
+            diagnostics.smoothed_y(),
+            diagnostics.last_alpha()
+        );
+        log_history(&history.snapshot());
+    });
+}
+
+// Sums up the recent history, stays quiet if it is turned off or empty
+fn log_history(history: &[HistoryEntry]) {
+    let (Some(first), Some(last)) = (history.first(), history.last()) else {
+        return;
+    };
+    let span = last
+        .timestamp
+        .duration_since(first.timestamp)
+        .unwrap_or_default();
+    let dropped = history
+        .iter()
+        .filter(|entry| entry.decision == ScrollDecision::Drop)
+        .count();
+    let sum = |delta: fn(&HistoryEntry) -> (f32, f32)| {
+        history
+            .iter()
+            .map(delta)
+            .fold((0.0, 0.0), |total, (x, y)| (total.0 + x, total.1 + y))
+    };
+    let raw = sum(|entry| entry.raw);
+    let smoothed = sum(|entry| entry.smoothed);
+    let mean_alpha = history.iter().map(|entry| entry.alpha).sum::<f32>() / history.len() as f32;
+    info!(
+        "Last {} scroll events over {:?}: {} dropped, raw distance ({}, {}), smoothed distance ({}, {}), mean alpha {}",
+        history.len(),
+        span,
+        dropped,
+        raw.0,
+        raw.1,
+        smoothed.0,
+        smoothed.1,
+        mean_alpha
+    );
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    initialize_logging()?;
+    let logging = Arc::new(Logging::initialize()?);
     info!("Starting application");
 
-    let config = read_config()?;
-    if config.log_to_file {
-        // Configure a file logger if log_to_file is enabled
-        let file_appender = tracing_appender::rolling::daily("logs", "app.log");
-        let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-        let file_subscriber = tracing_subscriber::FmtSubscriber::builder()
-            .with_writer(non_blocking)
-            .with_max_level(tracing::Level::INFO)
-            .finish();
-        tracing::subscriber::set_global_default(file_subscriber)?;
-        info!("Logging to file is enabled");
+    let args = parse_args()?;
+    // Before reading the config, which would create a default one without the comments
+    if args.init {
+        let config_path = init_config(args.config.as_deref())?;
+        info!("Wrote a new config to {:?}", config_path);
+        println!("Wrote a new config to {:?}", config_path);
+        return Ok(());
+    }
+    let config = read_config_or_default(args.config.as_deref(), args.config_override.as_deref());
+    // Profiles can turn on logging to the file while their app has focus
+    let any_log_to_file = config.log_to_file
+        || config
+            .profiles
+            .iter()
+            .any(|profile| profile.log_to_file == Some(true));
+    if any_log_to_file {
+        logging.open_file();
+    }
+    logging.apply(config.log_to_file, config.log_level);
+    if config.log_to_event_log {
+        logging.open_event_log();
+    }
+
+    if config.log_panics {
+        install_panic_hook();
     }
 
     info!("Config: {:?}", config);
 
-    // 1. Plot the scroll values
-    // 2. Plot the speed values
-    // 3. https://docs.google.com/spreadsheets/d/1irAZETTmwKNsD2Ho1e1_RrDXjAiplB_sUgW0JJKhyBM/edit#gid=0
-    // 4. Oh, so that's why the speed limiting works so well
-    let handler = EventHandler::new(EventHandlerConfig {
-        min_speed: 0.005,
-        force_start_distance: 3.9 / 120.0,
-        max_dropped_deltas: (30.0 / 120.0, 30.0 / 120.0),
-    });
-    let callback = move |event: Event| handler.callback(event);
-    if let Err(error) = grab(
-        EventTypes {
-            keyboard: false,
-            mouse: true,
-        },
-        callback,
-    ) {
-        error!("Error: {:?}", error);
+    if args.calibrate {
+        // With --script, the calibration analyses an earlier recording instead
+        return calibrate::run(config, args.config, args.script, args.write_config);
     }
 
-    Ok(())
-}
+    let remote_policy = if is_remote_session() {
+        info!(
+            "Running in a remote session, using {:?}",
+            config.remote_session_policy
+        );
+        config.remote_session_policy
+    } else {
+        info!("Running in a local session");
+        RemoteSessionPolicy::Ignore
+    };
 
-struct EventHandlerConfig {
-    min_speed: f32,
-    force_start_distance: f32,
-    max_dropped_deltas: (f32, f32),
-}
+    // The config wins if it sets `enabled`, otherwise the last runtime choice is restored.
+    // A remote session policy can turn it off regardless.
+    let (config_path, _) = resolve_config_path(args.config.as_deref());
+    let state_store = StateStore::new(&config_path);
+    let enabled = remote_policy != RemoteSessionPolicy::Disable
+        && config.enabled.unwrap_or_else(|| state_store.read().enabled);
+    info!("Smoothing enabled: {}", enabled);
 
-struct EventHandler {
-    last_scroll: Arc<Mutex<ScrollWithTimestamp>>,
-    dropped_deltas: Arc<Mutex<(f32, f32)>>,
-    config: EventHandlerConfig,
-    // For plotting the data
-    _start_time: time::SystemTime,
-}
+    let handler_config = EventHandlerConfig::from(&config);
+    // Keys are only grabbed if some feature listens to them
+    let grab_keyboard = handler_config.toggle_key.is_some()
+        || handler_config.zoom_modifier.is_some()
+        || handler_config.record_key.is_some()
+        || handler_config.replay_key.is_some()
+        || handler_config.velocity_lock_key.is_some()
+        || handler_config.log_boost_key.is_some();
 
-#[derive(Clone, Debug)]
-struct ScrollWithTimestamp {
-    delta_x: f32,
-    delta_y: f32,
-    timestamp: time::SystemTime,
-}
-
-impl Default for ScrollWithTimestamp {
-    fn default() -> Self {
-        ScrollWithTimestamp {
-            delta_x: 0.0,
-            delta_y: 0.0,
-            timestamp: time::SystemTime::UNIX_EPOCH,
-        }
-    }
-}
-
-impl EventHandler {
-    pub fn new(config: EventHandlerConfig) -> Self {
-        EventHandler {
-            last_scroll: Arc::new(Mutex::new(Default::default())),
-            dropped_deltas: Arc::new(Mutex::new((0.0, 0.0))),
-            config,
-            _start_time: time::SystemTime::now(),
-        }
+    if args.diagnose {
+        diagnose::run(
+            args.config.as_deref(),
+            args.config_override.as_deref(),
+            &handler_config,
+        );
+        return Ok(());
     }
 
-    pub fn callback(&self, event: Event) -> Option<Event> {
-        match event.event_type {
-            EventType::Wheel(MouseScrollDelta::LineDelta(delta_x, delta_y)) => {
-                let timestamp = event.time;
-                let should_keep_event = self.handle_mouse_scroll(timestamp, delta_x, delta_y);
-                if should_keep_event {
-                    *self.dropped_deltas.lock().unwrap() = (0.0, 0.0);
-                    Some(event)
-                } else {
-                    let mut dropped_deltas = self.dropped_deltas.lock().unwrap();
-                    *dropped_deltas = (dropped_deltas.0 + delta_x, dropped_deltas.1 + delta_y);
-                    // TODO: Do something with the dropped deltas
+    let trace = match (&config.trace_raw_file, &config.trace_output_file) {
+        (Some(raw_path), Some(output_path)) => {
+            match WheelTrace::create(raw_path.as_ref(), output_path.as_ref()) {
+                Ok(trace) => Some(Arc::new(trace)),
+                Err(error) => {
+                    error!("Could not create the wheel trace: {:?}", error);
                     None
                 }
             }
-            _ => Some(event),
         }
-    }
+        _ => None,
+    };
+    let with_trace = |emitter: Emitter| match &trace {
+        Some(trace) => emitter.with_trace(trace.clone()),
+        None => emitter,
+    };
 
-    fn handle_mouse_scroll(&self, timestamp: time::SystemTime, delta_x: f32, delta_y: f32) -> bool {
-        // Add new event
-        let last_delta = {
-            let mut last_delta_mutex = self.last_scroll.lock().unwrap();
-            let last_delta = last_delta_mutex.clone();
-
-            if timestamp >= last_delta.timestamp {
-                *last_delta_mutex = ScrollWithTimestamp {
-                    delta_x,
-                    delta_y,
-                    timestamp,
-                };
-            }
-            last_delta
-        };
+    if let Some(script_path) = args.script {
+        headless::check_config(&handler_config)?;
+        let (emitter, emitted) = Emitter::buffered();
+        let emitter = with_trace(emitter);
+        let handler = EventHandler::new(handler_config, enabled, emitter);
+        for event_type in headless::run(&handler, &emitted, &script_path)? {
+            println!("{:?}", event_type);
+        }
+        return Ok(());
+    }
 
-        let duration = match time::SystemTime::now().duration_since(last_delta.timestamp) {
-            Ok(duration) => duration,
-            Err(_) => {
-                // Shouldn't really happen. I'll just shoddily fake it then.
-                return false;
-            }
+    let output_backend = if remote_policy == RemoteSessionPolicy::WindowMessage {
+        OutputBackend::WindowMessage
+    } else {
+        config.output_backend
+    };
+    // Tests hand a fake clock to both instead
+    let clock = Arc::new(SystemClock);
+    let emitter = with_trace(Emitter::new(
+        output_backend,
+        config.synthetic_target,
+        Duration::from_millis(config.own_event_timeout_ms),
+    ))
+    .with_clock(clock.clone());
+    let handler = EventHandler::new(handler_config, enabled, emitter)
+        .with_clock(&*clock)
+        .with_state_store(state_store);
+    let handler = {
+        let logging = logging.clone();
+        let duration = Duration::from_secs(config.log_boost_secs);
+        handler.with_log_boost(move || logging.boost(duration))
+    };
+    let handler = if config.announce_command.is_empty() {
+        handler
+    } else {
+        let announcer = CommandAnnouncer::new(
+            config.announce_command.clone(),
+            Duration::from_millis(config.announce_min_interval_ms),
+        );
+        handler.with_line_listener(move |lines| announcer.announce(lines))
+    };
+    let handler = if config.profiles.is_empty() {
+        handler
+    } else {
+        let (log_to_file, log_level) = (config.log_to_file, config.log_level);
+        handler.with_profile_listener(move |profile| {
+            logging.apply(
+                profile
+                    .and_then(|profile| profile.log_to_file)
+                    .unwrap_or(log_to_file),
+                profile
+                    .and_then(|profile| profile.log_level)
+                    .unwrap_or(log_level),
+            )
+        })
+    };
+    if remote_policy == RemoteSessionPolicy::Profile {
+        handler
+            .controls()
+            .set_profile_override(config.remote_session_profile.clone());
+    }
+    if config.heartbeat_secs > 0 {
+        spawn_heartbeat(
+            handler.counters(),
+            handler.diagnostics(),
+            handler.history(),
+            handler.enabled(),
+            handler.active_profile_name(),
+            config_path.clone(),
+            Duration::from_secs(config.heartbeat_secs),
+        );
+    }
+    if config.control_port > 0 {
+        spawn_control_server(
+            config.control_port,
+            ControlState {
+                controls: handler.controls(),
+                profile_names: config
+                    .profiles
+                    .iter()
+                    .map(|profile| profile.name.clone())
+                    .collect(),
+                config: Mutex::new(config.clone()),
+                config_path: args.config.clone(),
+            },
+        );
+    }
+    if config.startup_delay_ms > 0 {
+        let delay = Duration::from_millis(config.startup_delay_ms);
+        info!("Waiting {:?} before grabbing the mouse", delay);
+        thread::sleep(delay);
+    }
+    if config.notify_on_start {
+        let controls = handler.controls();
+        let profile = match controls.profile_override() {
+            Some(name) => format!("profile {}", name),
+            None if config.profiles.is_empty() => "no profiles".to_string(),
+            None => "profile picked by the focused app".to_string(),
         };
-
-        let _sign_changed = (delta_x.signum() != last_delta.delta_x.signum())
-            || (delta_y.signum() != last_delta.delta_y.signum());
-
-        let speed_x = delta_x / (duration.as_millis() as f32);
-        let speed_y = delta_y / (duration.as_millis() as f32);
-
-        // If the delta is too small, we don't want to keep the event
-        return speed_x.abs() >= self.config.min_speed
-            || speed_y.abs() >= self.config.min_speed
-            || delta_x.abs() >= self.config.force_start_distance
-            || delta_y.abs() >= self.config.force_start_distance;
+        notify(
+            "Smoother scroll is running".to_string(),
+            format!(
+                "Smoothing {}, config {}, {}",
+                if controls.is_enabled() { "on" } else { "off" },
+                config_path.display(),
+                profile
+            ),
+        );
+    }
+    if config.wheel_watchdog_secs > 0 {
+        spawn_wheel_watchdog(
+            handler.wheel_event_seen(),
+            Duration::from_secs(config.wheel_watchdog_secs),
+        );
+    }
+    let callback = move |event: Event| handler.callback(event);
+    if let Err(error) = grab(
+        EventTypes {
+            keyboard: grab_keyboard,
+            mouse: true,
+        },
+        callback,
+    ) {
+        error!("Error: {:?}", error);
     }
+
+    Ok(())
 }
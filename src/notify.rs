@@ -0,0 +1,90 @@
+use std::thread;
+
+use tracing::warn;
+
+#[cfg(windows)]
+mod platform {
+    use std::{mem, ptr, thread, time::Duration};
+
+    use windows_sys::Win32::UI::Shell::{
+        Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_TIP, NIIF_INFO, NIM_ADD, NIM_DELETE,
+        NOTIFYICONDATAW,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DestroyWindow, LoadIconW, HWND_MESSAGE, IDI_APPLICATION,
+    };
+
+    // Windows hides the balloon on its own, the icon only has to outlive it
+    const ICON_DURATION: Duration = Duration::from_secs(10);
+
+    // Cuts the text off where it doesn't fit, and always leaves room for the terminating zero
+    fn copy_wide(target: &mut [u16], text: &str) {
+        let length = target.len() - 1;
+        for (slot, unit) in target.iter_mut().zip(text.encode_utf16().take(length)) {
+            *slot = unit;
+        }
+    }
+
+    // Shows a balloon from a temporary tray icon, which newer Windows versions turn into a toast.
+    // Blocks until the icon is removed again.
+    pub fn show(title: &str, message: &str) -> Result<(), String> {
+        let class: Vec<u16> = "STATIC\0".encode_utf16().collect();
+        // The icon needs a window to belong to, and a message-only one never shows up anywhere
+        let window = unsafe {
+            CreateWindowExW(
+                0,
+                class.as_ptr(),
+                ptr::null(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                0,
+                0,
+                ptr::null(),
+            )
+        };
+        if window == 0 {
+            return Err("Creating the notification window failed".to_string());
+        }
+
+        let mut data: NOTIFYICONDATAW = unsafe { mem::zeroed() };
+        data.cbSize = mem::size_of::<NOTIFYICONDATAW>() as u32;
+        data.hWnd = window;
+        data.uID = 1;
+        data.uFlags = NIF_ICON | NIF_TIP | NIF_INFO;
+        data.hIcon = unsafe { LoadIconW(0, IDI_APPLICATION) };
+        data.dwInfoFlags = NIIF_INFO;
+        copy_wide(&mut data.szTip, title);
+        copy_wide(&mut data.szInfoTitle, title);
+        copy_wide(&mut data.szInfo, message);
+
+        let result = if unsafe { Shell_NotifyIconW(NIM_ADD, &data) } == 0 {
+            Err("Adding the notification icon failed".to_string())
+        } else {
+            thread::sleep(ICON_DURATION);
+            unsafe { Shell_NotifyIconW(NIM_DELETE, &data) };
+            Ok(())
+        };
+        unsafe { DestroyWindow(window) };
+        result
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub fn show(_title: &str, _message: &str) -> Result<(), String> {
+        Err("Notifications are only supported on Windows".to_string())
+    }
+}
+
+// Shows a desktop notification without holding up the caller
+pub fn notify(title: String, message: String) {
+    thread::spawn(move || {
+        if let Err(error) = platform::show(&title, &message) {
+            warn!("Failed to show the notification: {}", error);
+        }
+    });
+}
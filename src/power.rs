@@ -0,0 +1,86 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Plugging in or unplugging is rare, checking once a second while scrolling is plenty
+const POWER_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::mem;
+
+    use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    use super::PowerSource;
+
+    pub fn power_source() -> Option<PowerSource> {
+        let mut status: SYSTEM_POWER_STATUS = unsafe { mem::zeroed() };
+        if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+            return None;
+        }
+        // 255 means that Windows doesn't know either
+        match status.ACLineStatus {
+            0 => Some(PowerSource::Battery),
+            1 => Some(PowerSource::Ac),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::PowerSource;
+
+    pub fn power_source() -> Option<PowerSource> {
+        None
+    }
+}
+
+// Whether the computer currently runs on battery, if the platform reports it
+pub fn power_source() -> Option<PowerSource> {
+    platform::power_source()
+}
+
+// Notices when the computer is plugged in or unplugged, by comparing to the last check.
+// It is only asked while scrolling, so that nothing has to run in the background.
+pub struct PowerWatcher {
+    last_source: Mutex<(Instant, Option<PowerSource>)>,
+}
+
+impl Default for PowerWatcher {
+    fn default() -> Self {
+        PowerWatcher {
+            last_source: Mutex::new((Instant::now(), power_source())),
+        }
+    }
+}
+
+impl PowerWatcher {
+    pub fn current(&self) -> Option<PowerSource> {
+        self.last_source.lock().unwrap().1
+    }
+
+    // The new power source, if it differs from the one at the last check. Within
+    // POWER_CHECK_INTERVAL of the last check, this returns None without asking the OS again.
+    pub fn changed(&self) -> Option<PowerSource> {
+        let now = Instant::now();
+        let mut last_source = self.last_source.lock().unwrap();
+        if now.duration_since(last_source.0) < POWER_CHECK_INTERVAL {
+            return None;
+        }
+        let source = power_source();
+        let (_, previous) = std::mem::replace(&mut *last_source, (now, source));
+        if previous != source {
+            source
+        } else {
+            None
+        }
+    }
+}
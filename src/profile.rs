@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+use crate::logging::LogLevel;
+
+// Keys that are pressed instead of scrolling, for apps that ignore the wheel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollKeys {
+    Arrows,
+    Pages,
+}
+
+impl ScrollKeys {
+    // Lines per key press, unless the profile says otherwise
+    pub fn default_lines_per_key(self) -> f32 {
+        match self {
+            ScrollKeys::Arrows => 1.0,
+            ScrollKeys::Pages => 20.0,
+        }
+    }
+}
+
+// Settings that only apply while one of the listed apps has focus.
+// Anything that is left unset falls back to the global setting.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    // Executable file names like "mstsc.exe", compared case-insensitively
+    pub executables: Vec<String>,
+    pub invert_x: Option<bool>,
+    pub invert_y: Option<bool>,
+    // Multiplies every scroll event before it is smoothed
+    pub speed_multiplier: Option<f32>,
+    pub force_integer_notches: Option<bool>,
+    pub log_to_file: Option<bool>,
+    pub log_level: Option<LogLevel>,
+    // Turns vertical scrolling into key presses
+    pub scroll_keys: Option<ScrollKeys>,
+    pub lines_per_key: Option<f32>,
+    // Turn flings and the timer output off, for example on battery
+    pub fling: Option<bool>,
+    pub timer_output: Option<bool>,
+}
+
+impl Profile {
+    pub fn matches(&self, executable: &str) -> bool {
+        self.executables
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(executable))
+    }
+}
+
+// The first matching profile wins
+pub fn find_profile<'a>(profiles: &'a [Profile], executable: Option<&str>) -> Option<&'a Profile> {
+    let executable = executable?;
+    profiles.iter().find(|profile| profile.matches(executable))
+}
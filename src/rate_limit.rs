@@ -0,0 +1,163 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use rdev::{EventType, MouseScrollDelta};
+
+use crate::deadline::DeadlineTimer;
+use crate::emitter::Emitter;
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct RateState {
+    // What was sent within the last window, oldest first
+    sent: VecDeque<(SystemTime, f32, f32)>,
+    // Distance that was held back, and is sent once the rate allows it
+    pending: (f32, f32),
+}
+
+impl RateState {
+    // Sends as much of the pending distance and the new delta as the rate allows at `timestamp`
+    fn send(
+        &mut self,
+        max_per_second: (f32, f32),
+        timestamp: SystemTime,
+        delta_x: f32,
+        delta_y: f32,
+    ) -> (f32, f32) {
+        while let Some(&(sent_at, _, _)) = self.sent.front() {
+            let age = timestamp.duration_since(sent_at).unwrap_or_default();
+            if age < WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+
+        let (sent_x, sent_y) = self
+            .sent
+            .iter()
+            .fold((0.0, 0.0), |(sum_x, sum_y), &(_, x, y)| {
+                (sum_x + x.abs(), sum_y + y.abs())
+            });
+        let output_x = clamp_to_rate(self.pending.0 + delta_x, sent_x, max_per_second.0);
+        let output_y = clamp_to_rate(self.pending.1 + delta_y, sent_y, max_per_second.1);
+
+        self.pending = (
+            self.pending.0 + delta_x - output_x,
+            self.pending.1 + delta_y - output_y,
+        );
+        if (output_x, output_y) != (0.0, 0.0) {
+            self.sent.push_back((timestamp, output_x, output_y));
+        }
+        (output_x, output_y)
+    }
+
+    // When the oldest sent distance leaves the window, and makes room for what is held back
+    fn next_release(&self) -> Option<SystemTime> {
+        if self.pending == (0.0, 0.0) {
+            return None;
+        }
+        self.sent.front().map(|&(sent_at, _, _)| sent_at + WINDOW)
+    }
+}
+
+// Bounds the sustained scroll throughput per axis, for fragile apps and remote desktop sessions.
+// Unlike a per event cap, fast scrolling is only slowed down. The held back distance is sent
+// with the next scroll events, or on its own once the rate allows it, so none of it is lost.
+pub struct RateLimiter {
+    // Lines per second, zero leaves that axis alone
+    max_per_second: (f32, f32),
+    state: Arc<Mutex<RateState>>,
+    release: DeadlineTimer,
+}
+
+impl RateLimiter {
+    pub fn new(max_x: f32, max_y: f32, emitter: Emitter) -> Self {
+        let max_per_second = (max_x, max_y);
+        let state = Arc::new(Mutex::new(RateState::default()));
+        let release = {
+            let state = state.clone();
+            DeadlineTimer::new(move || {
+                let mut state = state.lock().unwrap();
+                let (output_x, output_y) = state.send(max_per_second, SystemTime::now(), 0.0, 0.0);
+                let next_release = state.next_release();
+                drop(state);
+                if (output_x, output_y) != (0.0, 0.0) {
+                    emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+                        output_x, output_y,
+                    )));
+                }
+                next_release.map(instant_at)
+            })
+        };
+        RateLimiter {
+            max_per_second,
+            state,
+            release,
+        }
+    }
+
+    pub fn limit(&self, timestamp: SystemTime, delta_x: f32, delta_y: f32) -> (f32, f32) {
+        let mut state = self.state.lock().unwrap();
+        let output = state.send(self.max_per_second, timestamp, delta_x, delta_y);
+        if let Some(next_release) = state.next_release() {
+            self.release.set(instant_at(next_release));
+        }
+        output
+    }
+}
+
+// The deadline timer runs on the monotonic clock, while the sent distance is timed like the events
+fn instant_at(time: SystemTime) -> Instant {
+    Instant::now() + time.duration_since(SystemTime::now()).unwrap_or_default()
+}
+
+fn clamp_to_rate(delta: f32, sent: f32, max_per_second: f32) -> f32 {
+    if max_per_second <= 0.0 {
+        return delta;
+    }
+    let allowed = (max_per_second - sent).max(0.0);
+    delta.clamp(-allowed, allowed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_all_of_the_throttled_distance_in_the_end() {
+        let max_per_second = (0.0, 20.0);
+        let start = SystemTime::UNIX_EPOCH;
+        let mut state = RateState::default();
+        // A fast flick of 50 lines within 100 milliseconds
+        let mut total = 0.0;
+        for step in 0..10 {
+            let timestamp = start + Duration::from_millis(step * 10);
+            total += state.send(max_per_second, timestamp, 0.0, 5.0).1;
+        }
+        assert_eq!(total, 20.0);
+
+        // Without any more input, the rest goes out as the window makes room for it
+        let mut releases = 0;
+        while let Some(next_release) = state.next_release() {
+            let (_, released) = state.send(max_per_second, next_release, 0.0, 0.0);
+            assert!(released <= 20.0);
+            total += released;
+            releases += 1;
+            assert!(releases < 10, "the held back distance is never sent");
+        }
+        assert_eq!(total, 50.0);
+        assert_eq!(state.pending, (0.0, 0.0));
+    }
+
+    #[test]
+    fn leaves_an_axis_without_a_maximum_alone() {
+        let mut state = RateState::default();
+        let output = state.send((0.0, 1.0), SystemTime::UNIX_EPOCH, 100.0, 5.0);
+        assert_eq!(output, (100.0, 1.0));
+        assert_eq!(state.pending, (0.0, 4.0));
+    }
+}
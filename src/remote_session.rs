@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+// What changes while the app runs inside a remote desktop session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteSessionPolicy {
+    // Behaves like a local session
+    Ignore,
+    // Starts with smoothing turned off
+    Disable,
+    // Uses `remote_session_profile` for all apps
+    Profile,
+    // Posts window messages, since injected input doesn't always reach the session
+    WindowMessage,
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetSystemMetrics, SM_REMOTESESSION};
+
+    pub fn is_remote_session() -> bool {
+        unsafe { GetSystemMetrics(SM_REMOTESESSION) != 0 }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub fn is_remote_session() -> bool {
+        false
+    }
+}
+
+// True when running inside a remote desktop session, as far as the platform can tell
+pub fn is_remote_session() -> bool {
+    platform::is_remote_session()
+}
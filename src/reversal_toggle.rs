@@ -0,0 +1,79 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+#[derive(Default)]
+struct ReversalState {
+    // Sign of the current stroke, zero before the first one
+    direction: f32,
+    distance: f32,
+    // When each counted reversal happened, oldest first
+    reversals: VecDeque<SystemTime>,
+    // No new pattern starts before this, so that the strokes right after a toggle can't toggle again
+    cooldown_until: Option<SystemTime>,
+}
+
+// Recognizes quickly scrolling back and forth on the vertical axis, to toggle the smoothing
+// without a keyboard. A reversal only counts if the stroke before it went at least `min_distance`,
+// so that the wobble of a stopping wheel never adds up to the pattern.
+pub struct ReversalToggle {
+    count: u32,
+    window: Duration,
+    min_distance: f32,
+    state: Mutex<ReversalState>,
+}
+
+impl ReversalToggle {
+    pub fn new(count: u32, window: Duration, min_distance: f32) -> Self {
+        ReversalToggle {
+            count,
+            window,
+            min_distance,
+            state: Mutex::new(ReversalState::default()),
+        }
+    }
+
+    // Returns true on the reversal that completes the pattern
+    pub fn observe(&self, timestamp: SystemTime, delta_y: f32) -> bool {
+        if delta_y == 0.0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Some(cooldown_until) = state.cooldown_until {
+            if timestamp < cooldown_until {
+                return false;
+            }
+            state.cooldown_until = None;
+        }
+
+        let direction = delta_y.signum();
+        if direction == state.direction || state.direction == 0.0 {
+            state.direction = direction;
+            state.distance += delta_y.abs();
+            return false;
+        }
+        let counts = state.distance >= self.min_distance;
+        state.direction = direction;
+        state.distance = delta_y.abs();
+        if !counts {
+            state.reversals.clear();
+            return false;
+        }
+
+        state.reversals.push_back(timestamp);
+        while let Some(&first) = state.reversals.front() {
+            if timestamp.duration_since(first).unwrap_or_default() <= self.window {
+                break;
+            }
+            state.reversals.pop_front();
+        }
+        if state.reversals.len() < self.count as usize {
+            return false;
+        }
+        state.reversals.clear();
+        state.cooldown_until = Some(timestamp + self.window);
+        true
+    }
+}
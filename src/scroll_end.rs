@@ -0,0 +1,28 @@
+use std::time::{Duration, Instant};
+
+use rdev::{EventType, MouseScrollDelta};
+
+use crate::deadline::DeadlineTimer;
+use crate::emitter::Emitter;
+
+// Sends a zero scroll event once a gesture has ended, since some UI toolkits wait for one to
+// finish their own momentum animations. rdev has no scroll phases, so this is the only signal.
+pub struct ScrollEndNotifier {
+    timer: DeadlineTimer,
+    delay: Duration,
+}
+
+impl ScrollEndNotifier {
+    pub fn new(delay: Duration, emitter: Emitter) -> Self {
+        let timer = DeadlineTimer::new(move || {
+            emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(0.0, 0.0)));
+            None
+        });
+        ScrollEndNotifier { timer, delay }
+    }
+
+    // Called for every scroll event, the gesture ends once this hasn't been called for the delay
+    pub fn schedule(&self) {
+        self.timer.set(Instant::now() + self.delay);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use rdev::{EventType, MouseScrollDelta};
+
+use crate::emitter::Emitter;
+
+// Once this little is left on both axes, it is sent in one go
+const STOP_DISTANCE: f32 = 0.01;
+
+// Sends all scrolling at a fixed rate, decoupled from when the input events arrive.
+// Scroll events only move the target further, and every tick sends a share of the way that is
+// still left, so the output glides towards the target and ends up exactly as far. While nothing
+// is left to send, the thread blocks without a timer.
+pub struct TimerOutput {
+    backlog: Arc<Mutex<(f32, f32)>>,
+    interval: Arc<Mutex<Duration>>,
+    wake: mpsc::Sender<()>,
+}
+
+// Share of the way that is left, which one tick sends
+fn tick_share(interval: Duration, catch_up: Duration) -> f32 {
+    if catch_up.is_zero() {
+        1.0
+    } else {
+        1.0 - (-interval.as_secs_f32() / catch_up.as_secs_f32()).exp()
+    }
+}
+
+impl TimerOutput {
+    // `catch_up` is the time constant with which the output follows the target
+    pub fn new(interval: Duration, catch_up: Duration, emitter: Emitter) -> Self {
+        let backlog = Arc::new(Mutex::new((0.0f32, 0.0f32)));
+        let interval = Arc::new(Mutex::new(interval));
+        let (wake, receiver) = mpsc::channel::<()>();
+        {
+            let backlog = backlog.clone();
+            let interval = interval.clone();
+            thread::spawn(move || loop {
+                if *backlog.lock().unwrap() == (0.0, 0.0) {
+                    if receiver.recv().is_err() {
+                        return;
+                    }
+                    continue;
+                }
+                let interval = *interval.lock().unwrap();
+                thread::sleep(interval);
+                while receiver.try_recv().is_ok() {}
+                let share = tick_share(interval, catch_up);
+
+                let mut backlog = backlog.lock().unwrap();
+                let (backlog_x, backlog_y) = *backlog;
+                let step = if backlog_x.abs() <= STOP_DISTANCE && backlog_y.abs() <= STOP_DISTANCE {
+                    (backlog_x, backlog_y)
+                } else {
+                    (backlog_x * share, backlog_y * share)
+                };
+                *backlog = (backlog_x - step.0, backlog_y - step.1);
+                drop(backlog);
+                emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+                    step.0, step.1,
+                )));
+            });
+        }
+        TimerOutput {
+            backlog,
+            interval,
+            wake,
+        }
+    }
+
+    // Takes effect from the next tick
+    pub fn set_interval(&self, interval: Duration) {
+        *self.interval.lock().unwrap() = interval;
+    }
+
+    pub fn add(&self, delta_x: f32, delta_y: f32) {
+        let mut backlog = self.backlog.lock().unwrap();
+        let was_idle = *backlog == (0.0, 0.0);
+        *backlog = (backlog.0 + delta_x, backlog.1 + delta_y);
+        if was_idle {
+            let _ = self.wake.send(());
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    fs::File,
+    io::{LineWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use tracing::{error, info};
+
+const HEADER: &str = "sequence,milliseconds,delta_x,delta_y\n";
+
+// Writes the wheel input and the wheel output to two CSV files, for comparing filters offline.
+// Every input event gets the next sequence number, and the output it causes is written with the
+// same number. Output of timers, like merged bursts or flushed distance, gets the number of
+// the last input event before it. Both files share one clock, so lag can be read off directly.
+pub struct WheelTrace {
+    raw: Mutex<LineWriter<File>>,
+    output: Mutex<LineWriter<File>>,
+    sequence: AtomicU64,
+    start_time: Instant,
+}
+
+impl WheelTrace {
+    pub fn create(raw_path: &Path, output_path: &Path) -> std::io::Result<Self> {
+        let open = |path: &Path| -> std::io::Result<_> {
+            let mut writer = LineWriter::new(File::create(path)?);
+            writer.write_all(HEADER.as_bytes())?;
+            Ok(Mutex::new(writer))
+        };
+        let trace = WheelTrace {
+            raw: open(raw_path)?,
+            output: open(output_path)?,
+            sequence: AtomicU64::new(0),
+            start_time: Instant::now(),
+        };
+        info!(
+            "Tracing the wheel input to {:?} and the output to {:?}",
+            raw_path, output_path
+        );
+        Ok(trace)
+    }
+
+    pub fn raw(&self, delta_x: f32, delta_y: f32) {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        self.write(&self.raw, sequence, delta_x, delta_y);
+    }
+
+    pub fn output(&self, delta_x: f32, delta_y: f32) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        self.write(&self.output, sequence, delta_x, delta_y);
+    }
+
+    fn write(&self, writer: &Mutex<LineWriter<File>>, sequence: u64, delta_x: f32, delta_y: f32) {
+        let millis = self.start_time.elapsed().as_secs_f64() * 1000.0;
+        let line = format!("{},{:.3},{},{}\n", sequence, millis, delta_x, delta_y);
+        if let Err(error) = writer.lock().unwrap().write_all(line.as_bytes()) {
+            error!("Failed to write the wheel trace: {:?}", error);
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+// A scroll distance in lines, the unit that rdev reports wheel deltas in.
+// One notch of a regular wheel is one line, which the OS reports as 120 wheel units.
+// High resolution wheels like the MX Master report fractions of that.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Lines(pub f32);
+
+impl Lines {
+    pub const WHEEL_UNITS_PER_LINE: f32 = 120.0;
+
+    pub const fn from_wheel_units(wheel_units: f32) -> Self {
+        Lines(wheel_units / Self::WHEEL_UNITS_PER_LINE)
+    }
+
+    pub fn abs(self) -> Self {
+        Lines(self.0.abs())
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rdev::{EventType, MouseScrollDelta};
+use tracing::info;
+
+use crate::emitter::Emitter;
+
+// Roughly one frame at 60 Hz
+const TICK: Duration = Duration::from_millis(16);
+
+// Keeps scrolling at a fixed speed hands-free, for reading long documents.
+// Every lock starts a new generation, and a sending thread stops once its generation is over.
+pub struct VelocityLock {
+    generation: Arc<AtomicU64>,
+    active_generation: AtomicU64,
+    max_duration: Duration,
+    emitter: Emitter,
+}
+
+impl VelocityLock {
+    pub fn new(max_duration: Duration, emitter: Emitter) -> Self {
+        VelocityLock {
+            generation: Arc::new(AtomicU64::new(0)),
+            active_generation: AtomicU64::new(0),
+            max_duration,
+            emitter,
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        let active_generation = self.active_generation.load(Ordering::Relaxed);
+        active_generation != 0 && active_generation == self.generation.load(Ordering::Relaxed)
+    }
+
+    // Velocity in lines per millisecond
+    pub fn toggle(&self, velocity_x: f32, velocity_y: f32) {
+        if self.release() {
+            return;
+        }
+        if velocity_x == 0.0 && velocity_y == 0.0 {
+            info!("Not scrolling, nothing to lock");
+            return;
+        }
+
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.active_generation.store(generation, Ordering::Relaxed);
+        info!(
+            "Locked scroll velocity at ({}, {}) lines per millisecond",
+            velocity_x, velocity_y
+        );
+
+        let current_generation = self.generation.clone();
+        let max_duration = self.max_duration;
+        let emitter = self.emitter.clone();
+        let millis = TICK.as_secs_f32() * 1000.0;
+        thread::spawn(move || {
+            let started = Instant::now();
+            loop {
+                thread::sleep(TICK);
+                if current_generation.load(Ordering::Relaxed) != generation {
+                    return;
+                }
+                if started.elapsed() >= max_duration {
+                    info!("Scroll velocity lock ran out after {:?}", max_duration);
+                    current_generation.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                emitter.emit(EventType::Wheel(MouseScrollDelta::LineDelta(
+                    velocity_x * millis,
+                    velocity_y * millis,
+                )));
+            }
+        });
+    }
+
+    // Stops a running lock, and returns whether there was one
+    pub fn release(&self) -> bool {
+        if !self.is_locked() {
+            return false;
+        }
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        info!("Released scroll velocity lock");
+        true
+    }
+}
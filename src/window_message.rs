@@ -0,0 +1,56 @@
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::Foundation::POINT;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetCursorPos, GetForegroundWindow, PostMessageW, WindowFromPoint, WM_MOUSEHWHEEL,
+        WM_MOUSEWHEEL,
+    };
+
+    use crate::emitter::ScrollTarget;
+    use crate::units::Lines;
+
+    pub const SUPPORTED: bool = true;
+
+    pub fn send_wheel(delta_x: f32, delta_y: f32, target: ScrollTarget) -> Result<(), String> {
+        let mut cursor = POINT { x: 0, y: 0 };
+        unsafe { GetCursorPos(&mut cursor) };
+        let window = match target {
+            ScrollTarget::Focus => unsafe { GetForegroundWindow() },
+            // The innermost window under the cursor, which is usually the control to scroll
+            ScrollTarget::Cursor => unsafe { WindowFromPoint(cursor) },
+        };
+        if window == 0 {
+            return Err(format!("No window to scroll for {:?}", target));
+        }
+        // The cursor position is packed into the low and high word, like the OS does
+        let position = (cursor.x as u16 as isize) | ((cursor.y as u16 as isize) << 16);
+
+        for (message, delta) in [(WM_MOUSEWHEEL, delta_y), (WM_MOUSEHWHEEL, delta_x)] {
+            if delta == 0.0 {
+                continue;
+            }
+            let wheel_units = (delta * Lines::WHEEL_UNITS_PER_LINE).round() as i16;
+            let wparam = (wheel_units as u16 as usize) << 16;
+            if unsafe { PostMessageW(window, message, wparam, position) } == 0 {
+                return Err(format!(
+                    "Posting {:#x} to the {:?} window failed",
+                    message, target
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use crate::emitter::ScrollTarget;
+
+    pub const SUPPORTED: bool = false;
+
+    pub fn send_wheel(_delta_x: f32, _delta_y: f32, _target: ScrollTarget) -> Result<(), String> {
+        Err("Window messages are only supported on Windows".to_string())
+    }
+}
+
+pub use platform::{send_wheel, SUPPORTED};
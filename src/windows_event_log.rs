@@ -0,0 +1,192 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+        EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+    };
+    use windows_sys::Win32::System::Registry::{
+        RegCloseKey, RegCreateKeyExW, RegOpenKeyExW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE,
+        KEY_READ, KEY_SET_VALUE, REG_DWORD, REG_EXPAND_SZ, REG_OPTION_NON_VOLATILE,
+    };
+
+    use super::Severity;
+
+    // Name that the entries are listed under in the Event Viewer
+    const SOURCE_NAME: &str = "Smoother Scroll";
+    const SOURCE_KEY: &str =
+        "SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\Smoother Scroll";
+    // The message table of eventcreate.exe shows events 1 to 1000 with their text as it is,
+    // so the app doesn't need a message file of its own
+    const MESSAGE_FILE: &str = "%SystemRoot%\\System32\\EventCreate.exe";
+    const EVENT_ID: u32 = 1;
+    // Errors, warnings and information
+    const TYPES_SUPPORTED: u32 = 7;
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn set_value(key: HKEY, name: &str, kind: u32, data: &[u8]) -> Result<(), String> {
+        let name = wide(name);
+        let result = unsafe {
+            RegSetValueExW(
+                key,
+                name.as_ptr(),
+                0,
+                kind,
+                data.as_ptr(),
+                data.len() as u32,
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return Err(format!(
+                "Setting the registry value failed with error {}",
+                result
+            ));
+        }
+        Ok(())
+    }
+
+    // Creating the registry key of the source needs administrator rights, but only once
+    pub fn install() -> Result<(), String> {
+        let source_key = wide(SOURCE_KEY);
+        let mut key: HKEY = 0;
+        let result = unsafe {
+            RegOpenKeyExW(
+                HKEY_LOCAL_MACHINE,
+                source_key.as_ptr(),
+                0,
+                KEY_READ,
+                &mut key,
+            )
+        };
+        if result == ERROR_SUCCESS {
+            unsafe { RegCloseKey(key) };
+            return Ok(());
+        }
+
+        let result = unsafe {
+            RegCreateKeyExW(
+                HKEY_LOCAL_MACHINE,
+                source_key.as_ptr(),
+                0,
+                ptr::null(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_SET_VALUE,
+                ptr::null(),
+                &mut key,
+                ptr::null_mut(),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return Err(format!(
+                "Creating the registry key failed with error {}",
+                result
+            ));
+        }
+        let message_file: Vec<u8> = wide(MESSAGE_FILE)
+            .iter()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        let result =
+            set_value(key, "EventMessageFile", REG_EXPAND_SZ, &message_file).and_then(|()| {
+                set_value(
+                    key,
+                    "TypesSupported",
+                    REG_DWORD,
+                    &TYPES_SUPPORTED.to_le_bytes(),
+                )
+            });
+        unsafe { RegCloseKey(key) };
+        result
+    }
+
+    pub struct Source(HANDLE);
+
+    impl Source {
+        pub fn open() -> Result<Self, String> {
+            let name = wide(SOURCE_NAME);
+            let handle = unsafe { RegisterEventSourceW(ptr::null(), name.as_ptr()) };
+            if handle == 0 {
+                return Err("Opening the event source failed".to_string());
+            }
+            Ok(Source(handle))
+        }
+
+        pub fn report(&self, severity: Severity, message: &str) {
+            let kind = match severity {
+                Severity::Error => EVENTLOG_ERROR_TYPE,
+                Severity::Warning => EVENTLOG_WARNING_TYPE,
+                Severity::Information => EVENTLOG_INFORMATION_TYPE,
+            };
+            let message = wide(message);
+            let strings = [message.as_ptr()];
+            unsafe {
+                ReportEventW(
+                    self.0,
+                    kind,
+                    0,
+                    EVENT_ID,
+                    ptr::null_mut(),
+                    1,
+                    0,
+                    strings.as_ptr(),
+                    ptr::null(),
+                )
+            };
+        }
+    }
+
+    impl Drop for Source {
+        fn drop(&mut self) {
+            unsafe { DeregisterEventSource(self.0) };
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::Severity;
+
+    pub fn install() -> Result<(), String> {
+        Err("The Windows Event Log is only available on Windows".to_string())
+    }
+
+    pub struct Source;
+
+    impl Source {
+        pub fn open() -> Result<Self, String> {
+            Err("The Windows Event Log is only available on Windows".to_string())
+        }
+
+        pub fn report(&self, _severity: Severity, _message: &str) {}
+    }
+}
+
+// Registers the app as a source of the Application log, if it isn't one yet
+pub fn install() -> Result<(), String> {
+    platform::install()
+}
+
+// Where log entries are reported to the Windows Event Log
+pub struct EventSource(platform::Source);
+
+impl EventSource {
+    pub fn open() -> Result<Self, String> {
+        platform::Source::open().map(EventSource)
+    }
+
+    pub fn report(&self, severity: Severity, message: &str) {
+        self.0.report(severity, message);
+    }
+}